@@ -0,0 +1,14 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use mongodb_schema_parser::SchemaParser;
+
+// Concatenated BSON documents, like a `.bson` file of `mongodump`, and the
+// same bytes as a `mongodump --archive` file.
+fuzz_target!(|data: &[u8]| {
+  let mut schema_parser = SchemaParser::new();
+  schema_parser.set_lenient(true);
+  let _ = schema_parser.write_bson_reader(data);
+  let _ = schema_parser.to_compass();
+  let _ = schema_parser.anomalies();
+  let _ = SchemaParser::from_archive(data);
+});
@@ -0,0 +1,16 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use mongodb_schema_parser::{CsvOptions, SchemaParser};
+
+// CSV with typed headers, i.e. `born.date(2006-01-02)`, which have their own
+// parsers for dates and binary encodings.
+fuzz_target!(|data: &[u8]| {
+  let options = CsvOptions {
+    columns_have_types: true,
+    ..CsvOptions::default()
+  };
+  let mut schema_parser = SchemaParser::new();
+  schema_parser.set_lenient(true);
+  let _ = schema_parser.write_csv_reader(data, &options);
+  let _ = schema_parser.flush().into_json();
+});
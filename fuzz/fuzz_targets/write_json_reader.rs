@@ -0,0 +1,11 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use mongodb_schema_parser::SchemaParser;
+
+// Newline-delimited json or a json array of Extended JSON documents.
+fuzz_target!(|data: &[u8]| {
+  let mut schema_parser = SchemaParser::new();
+  schema_parser.set_lenient(true);
+  let _ = schema_parser.write_json_reader(data);
+  let _ = schema_parser.flush().into_json();
+});
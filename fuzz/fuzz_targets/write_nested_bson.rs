@@ -0,0 +1,27 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use mongodb_schema_parser::SchemaParser;
+
+// Documents and arrays nested as deep as the input is long, which random
+// bytes hardly ever produce. Every byte adds a level: a document if it is
+// even and an array if it is odd.
+fuzz_target!(|data: &[u8]| {
+  let mut bytes = Vec::new();
+  for (level, kind) in data.iter().enumerate() {
+    let length = 5 + 8 * (data.len() - level);
+    bytes.extend_from_slice(&(length as i32).to_le_bytes());
+    let (element_type, key) = if kind % 2 == 0 {
+      (0x03, b'a')
+    } else {
+      (0x04, b'0')
+    };
+    bytes.extend_from_slice(&[element_type, key, 0x00]);
+  }
+  bytes.extend_from_slice(&[0x05, 0x00, 0x00, 0x00, 0x00]);
+  bytes.resize(bytes.len() + data.len(), 0x00);
+
+  let mut schema_parser = SchemaParser::new();
+  schema_parser.set_lenient(true);
+  let _ = schema_parser.write_bson_reader(bytes.as_slice());
+  let _ = schema_parser.flush().into_json();
+});
@@ -0,0 +1,185 @@
+use super::{ratio, Result, SchemaParser, ValueType};
+use std::fmt::{self, Display};
+
+/// Types making up a smaller share of a field than this are reported by
+/// `SchemaParser::anomalies`.
+pub static DEFAULT_ANOMALY_THRESHOLD: f32 = 0.01;
+
+/// How many example values are kept for every anomaly.
+pub static MAX_EXAMPLES: usize = 5;
+
+/// Rare types found in fields that have more than one type, as returned by
+/// `SchemaParser::anomalies`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct AnomalyReport {
+  pub anomalies: Vec<Anomaly>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Anomaly {
+  pub path: String,
+  pub bson_type: String,
+  pub count: usize,
+  /// Share of the documents that have this field.
+  pub probability: f32,
+  /// The most common type of this field.
+  pub dominant_type: String,
+  pub examples: Vec<ValueType>,
+  /// `_id`s of documents with this type. Only the first few are kept, and
+  /// only for documents that have an `_id`.
+  pub document_ids: Vec<ValueType>,
+}
+
+impl AnomalyReport {
+  /// Returns `true` if no rare types were found.
+  pub fn is_empty(&self) -> bool {
+    self.anomalies.is_empty()
+  }
+
+  /// Returns the report as a serde_json string.
+  pub fn to_json(&self) -> Result<String> {
+    Ok(serde_json::to_string(self)?)
+  }
+
+  pub(crate) fn new(schema: &SchemaParser, threshold: f32) -> Self {
+    let mut anomalies = Vec::new();
+    schema.walk_fields(&mut |field, _| {
+      let present = field.present_count();
+      let types: Vec<(usize, _)> = field
+        .types
+        .values()
+        .map(|field_type| (field.type_count(field_type), field_type))
+        .filter(|(count, _)| *count > 0)
+        .collect();
+      if types.len() < 2 {
+        return;
+      }
+      let dominant = match types.iter().max_by_key(|(count, _)| *count) {
+        Some((_, field_type)) => field_type.bson_type.to_string(),
+        None => return,
+      };
+
+      for (count, field_type) in &types {
+        let probability = ratio(*count, present);
+        if probability >= threshold {
+          continue;
+        }
+        anomalies.push(Anomaly {
+          path: field.path.to_string(),
+          bson_type: field_type.bson_type.to_string(),
+          count: *count,
+          probability,
+          dominant_type: dominant.to_string(),
+          examples: field_type
+            .values
+            .iter()
+            .take(MAX_EXAMPLES)
+            .cloned()
+            .collect(),
+          document_ids: field_type.document_ids.clone(),
+        });
+      }
+    });
+    anomalies.sort_by(|a, b| {
+      a.path
+        .cmp(&b.path)
+        .then_with(|| a.bson_type.cmp(&b.bson_type))
+    });
+    AnomalyReport { anomalies }
+  }
+}
+
+impl Display for AnomalyReport {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.is_empty() {
+      return writeln!(f, "no anomalies");
+    }
+    for anomaly in &self.anomalies {
+      write!(
+        f,
+        "{}: {} {} value(s) ({:.2}%) in a field that is mostly {}",
+        anomaly.path,
+        anomaly.count,
+        anomaly.bson_type,
+        anomaly.probability * 100.0,
+        anomaly.dominant_type
+      )?;
+      if !anomaly.examples.is_empty() {
+        write!(f, ", e.g. {}", list(&anomaly.examples))?;
+      }
+      if !anomaly.document_ids.is_empty() {
+        write!(f, " in _id {}", list(&anomaly.document_ids))?;
+      }
+      writeln!(f)?;
+    }
+    Ok(())
+  }
+}
+
+fn list(values: &[ValueType]) -> String {
+  values
+    .iter()
+    .map(|value| serde_json::to_string(value).unwrap_or_default())
+    .collect::<Vec<String>>()
+    .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use bson::doc;
+
+  fn schema() -> SchemaParser {
+    let mut schema_parser = SchemaParser::new();
+    for i in 0..200 {
+      schema_parser
+        .write_bson(doc! {"_id": i, "age": i, "owner": {"age": 30}})
+        .unwrap();
+    }
+    schema_parser
+      .write_bson(doc! {"_id": 200, "age": "two", "owner": {"age": "thirty"}})
+      .unwrap();
+    schema_parser
+  }
+
+  #[test]
+  fn it_finds_rare_types() {
+    let report = schema().anomalies();
+    assert_eq!(report.anomalies.len(), 2);
+    let anomaly = &report.anomalies[0];
+    assert_eq!(anomaly.path, "age");
+    assert_eq!(anomaly.bson_type, "String");
+    assert_eq!(anomaly.dominant_type, "Int32");
+    assert_eq!(anomaly.count, 1);
+    assert_eq!(anomaly.examples, vec![ValueType::Str("two".to_string())]);
+    assert_eq!(anomaly.document_ids, vec![ValueType::I32(200)]);
+  }
+
+  #[test]
+  fn it_keeps_document_ids_for_nested_fields() {
+    let report = schema().anomalies();
+    let anomaly = &report.anomalies[1];
+    assert_eq!(anomaly.path, "owner.age");
+    assert_eq!(anomaly.document_ids, vec![ValueType::I32(200)]);
+  }
+
+  #[test]
+  fn it_uses_threshold() {
+    assert!(schema().anomalies_with_threshold(0.001).is_empty());
+  }
+
+  #[test]
+  fn it_ignores_missing_fields() {
+    let mut schema_parser = SchemaParser::new();
+    for i in 0..200 {
+      schema_parser.write_bson(doc! {"age": i}).unwrap();
+    }
+    schema_parser.write_bson(doc! {"name": "Nori"}).unwrap();
+    schema_parser.flush();
+    assert!(schema_parser
+      .anomalies()
+      .anomalies
+      .iter()
+      .all(|anomaly| anomaly.path != "age"));
+  }
+}
@@ -0,0 +1,276 @@
+use super::ingest::hex;
+use super::reader::{read_full, Block, BsonReader};
+use super::{
+  Error, ErrorKind, Location, Result, SchemaParser, SchemaParserOptions,
+};
+use bson::Document;
+use std::collections::HashMap;
+use std::io::Read;
+
+// The first four bytes of every `mongodump --archive` file, little-endian.
+static MAGIC_NUMBER: u32 = 0x8199_e26d;
+
+// Reads an archive as written by `mongodump --archive`:
+//
+// * the magic number
+// * the prelude: a header document, one metadata document per collection and
+//   a terminator
+// * any number of segments: a namespace header document, that namespace's
+//   documents and a terminator. Dumps of several collections interleave their
+//   segments; a namespace header with `EOF: true` closes a namespace.
+//
+// In lenient mode, malformed documents in a segment are skipped and recorded
+// in the `ingest_report` of their namespace. The prelude and namespace
+// headers are needed to follow the archive, so they are never skipped.
+pub(crate) fn read_archive<R: Read>(
+  mut reader: R,
+  options: &SchemaParserOptions,
+  lenient: bool,
+) -> Result<HashMap<String, SchemaParser>> {
+  // checked once here rather than for every namespace
+  options.check()?;
+  let new_schema = || {
+    let mut schema = SchemaParser {
+      options: options.clone(),
+      ..SchemaParser::new()
+    };
+    schema.set_lenient(lenient);
+    schema
+  };
+  let mut magic = [0u8; 4];
+  if read_full(&mut reader, &mut magic)? < magic.len()
+    || u32::from_le_bytes(magic) != MAGIC_NUMBER
+  {
+    return Err(invalid("not a mongodump archive", 0));
+  }
+
+  let mut reader = BsonReader::with_offset(reader, magic.len() as u64);
+  let mut schemas = HashMap::new();
+
+  let offset = reader.offset();
+  if !matches!(reader.read_block()?, Some(Block::Document(_))) {
+    return Err(invalid("expected the archive prelude", offset));
+  }
+  while let Some(metadata) = read_document(&mut reader)? {
+    // views have no documents of their own
+    if metadata.get_str("type").ok() == Some("view") {
+      continue;
+    }
+    if let Some(namespace) = namespace(&metadata) {
+      schemas.entry(namespace).or_insert_with(new_schema);
+    }
+  }
+
+  loop {
+    let offset = reader.offset();
+    let header = match reader.read_block()? {
+      Some(Block::Document(header)) => header,
+      Some(Block::Malformed { error, .. }) => {
+        return Err(Error::from(error).at(Location::Offset(offset)))
+      }
+      Some(Block::Terminator) => {
+        return Err(invalid("expected a namespace header", offset))
+      }
+      None => break,
+    };
+    // `mongodump --oplog` stores the oplog in a namespace without a database
+    let schema = namespace(&header)
+      .map(|namespace| schemas.entry(namespace).or_insert_with(new_schema));
+    write_segment(&mut reader, schema, lenient)?;
+  }
+
+  Ok(schemas)
+}
+
+// Writes the documents of a segment up to its terminator, leaving them out
+// if there is no `schema` to write them to.
+fn write_segment<R: Read>(
+  reader: &mut BsonReader<R>,
+  mut schema: Option<&mut SchemaParser>,
+  lenient: bool,
+) -> Result<()> {
+  loop {
+    let offset = reader.offset();
+    match reader.read_block()? {
+      Some(Block::Document(doc)) => {
+        if let Some(schema) = schema.as_mut() {
+          if let Err(e) = schema.write_bson(doc) {
+            schema.skip(e.at(Location::Offset(offset)), "")?;
+          }
+        }
+      }
+      Some(Block::Malformed { bytes, error }) => {
+        let e = Error::from(error).at(Location::Offset(offset));
+        match schema.as_mut() {
+          Some(schema) => schema.skip(e, &hex(&bytes))?,
+          None if lenient => {}
+          None => return Err(e),
+        }
+      }
+      Some(Block::Terminator) => return Ok(()),
+      None => return Err(invalid("truncated archive", offset)),
+    }
+  }
+}
+
+// Reads the next document of a block, or `None` at its terminator.
+fn read_document<R: Read>(
+  reader: &mut BsonReader<R>,
+) -> Result<Option<Document>> {
+  let offset = reader.offset();
+  match reader.read_block()? {
+    Some(block) => match block.strict(offset)? {
+      Block::Document(doc) => Ok(Some(doc)),
+      _ => Ok(None),
+    },
+    None => Err(invalid("truncated archive", offset)),
+  }
+}
+
+fn invalid(message: &str, offset: u64) -> Error {
+  Error::from(ErrorKind::InvalidInput(message.to_string()))
+    .at(Location::Offset(offset))
+}
+
+fn namespace(doc: &Document) -> Option<String> {
+  match (doc.get_str("db"), doc.get_str("collection")) {
+    (Ok(db), Ok(collection)) if !db.is_empty() => {
+      Some(format!("{}.{}", db, collection))
+    }
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use bson::doc;
+
+  fn read(bytes: &[u8]) -> Result<HashMap<String, SchemaParser>> {
+    read_archive(bytes, &SchemaParserOptions::default(), false)
+  }
+
+  struct Archive(Vec<u8>);
+
+  impl Archive {
+    fn new(collections: &[(&str, &str)]) -> Self {
+      let mut archive = Archive(MAGIC_NUMBER.to_le_bytes().to_vec());
+      archive.document(doc! {
+        "concurrent_collections": 4,
+        "version": "0.1",
+        "server_version": "5.0.0",
+        "tool_version": "100.5.2",
+      });
+      for (db, collection) in collections {
+        archive.document(doc! {
+          "db": db,
+          "collection": collection,
+          "metadata": "{}",
+          "size": 0,
+          "type": "collection",
+        });
+      }
+      archive.terminator();
+      archive
+    }
+
+    fn segment(&mut self, collection: &str, docs: Vec<Document>) {
+      self.document(doc! {"db": "zoo", "collection": collection});
+      for doc in docs {
+        self.document(doc);
+      }
+      self.terminator();
+    }
+
+    fn eof(&mut self, collection: &str) {
+      self.document(doc! {
+        "db": "zoo",
+        "collection": collection,
+        "EOF": true,
+        "CRC": 0i64,
+      });
+      self.terminator();
+    }
+
+    fn document(&mut self, doc: Document) {
+      doc.to_writer(&mut self.0).unwrap();
+    }
+
+    fn terminator(&mut self) {
+      self.0.extend_from_slice(&[0xff; 4]);
+    }
+  }
+
+  #[test]
+  fn it_reads_interleaved_namespaces() {
+    let mut archive = Archive::new(&[("zoo", "cats"), ("zoo", "dogs")]);
+    archive.segment("cats", vec![doc! {"name": "Nori"}]);
+    archive.segment("dogs", vec![doc! {"name": "Rey", "age": 2}]);
+    archive.segment("cats", vec![doc! {"name": "Chashu"}]);
+    archive.eof("dogs");
+    archive.eof("cats");
+
+    let schemas = read(archive.0.as_slice()).unwrap();
+    assert_eq!(schemas.len(), 2);
+    assert_eq!(schemas["zoo.cats"].count, 2);
+    assert_eq!(schemas["zoo.dogs"].count, 1);
+    assert_eq!(schemas["zoo.dogs"].fields.len(), 2);
+  }
+
+  #[test]
+  fn it_keeps_empty_collections() {
+    let mut archive = Archive::new(&[("zoo", "birds")]);
+    archive.eof("birds");
+    let schemas = read(archive.0.as_slice()).unwrap();
+    assert_eq!(schemas["zoo.birds"].count, 0);
+  }
+
+  #[test]
+  fn it_rejects_other_files() {
+    let mut bytes = Vec::new();
+    doc! {"name": "Nori"}.to_writer(&mut bytes).unwrap();
+    let error = read(bytes.as_slice()).unwrap_err();
+    assert_eq!(error.to_string(), "byte offset 0: not a mongodump archive");
+  }
+
+  #[test]
+  fn it_skips_malformed_documents_in_lenient_mode() {
+    let mut archive = Archive::new(&[("zoo", "cats")]);
+    archive.document(doc! {"db": "zoo", "collection": "cats"});
+    archive.document(doc! {"name": "Nori"});
+    // a valid length with a string missing its terminating null byte
+    let offset = archive.0.len() as u64;
+    archive.0.extend_from_slice(&[
+      0x10, 0x00, 0x00, 0x00, 0x02, b'a', 0x00, 0x02, 0x00, 0x00, 0x00, b'b',
+      b'c', 0x00, 0x00, 0x00,
+    ]);
+    archive.document(doc! {"name": "Rey"});
+    archive.terminator();
+    archive.eof("cats");
+
+    let error = read(archive.0.as_slice()).unwrap_err();
+    assert!(error
+      .to_string()
+      .starts_with(&format!("byte offset {}: ", offset)));
+
+    let options = SchemaParserOptions::default();
+    let schemas = read_archive(archive.0.as_slice(), &options, true).unwrap();
+    let cats = &schemas["zoo.cats"];
+    assert_eq!(cats.count, 2);
+    let report = cats.ingest_report();
+    assert_eq!(report.skipped, 1);
+    assert_eq!(report.errors[0].location, Location::Offset(offset));
+  }
+
+  #[test]
+  fn it_reports_truncated_archives() {
+    let mut archive = Archive::new(&[("zoo", "cats")]);
+    archive.document(doc! {"db": "zoo", "collection": "cats"});
+    let length = archive.0.len();
+    let error = read(archive.0.as_slice()).unwrap_err();
+    assert_eq!(
+      error.to_string(),
+      format!("byte offset {}: truncated archive", length)
+    );
+  }
+}
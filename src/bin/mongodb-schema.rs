@@ -0,0 +1,331 @@
+//! Infers a schema from newline-delimited JSON, `.bson` dump or CSV files and
+//! prints it. Gzip and zstd compressed files are read when built with the
+//! `gzip` and `zstd` features.
+//!
+//! ```sh
+//! $ mongodb-schema cats.json dogs.json
+//! $ mongoexport -d zoo -c cats --jsonArray | mongodb-schema --format pretty
+//! $ mongodb-schema --format compass dump/zoo/cats.bson.gz
+//! $ mongodb-schema --archive zoo.archive
+//! $ mongodb-schema --columns-have-types cats.csv
+//! $ mongodb-schema --lenient export.json
+//! $ mongodb-schema --max-depth 2 --max-values 0 cats.json
+//! $ mongodb-schema --exclude 'payload.**' --exclude '*.internal' events.json
+//! $ mongodb-schema --redact '**.email' users.json
+//! ```
+
+use clap::{ArgEnum, Parser};
+use mongodb_schema_parser::{
+  CsvOptions, OnLimit, Redaction, SchemaParser, SchemaParserOptions,
+};
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process;
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+// Environment variable holding the salt of `--hash-redacted`, which would be
+// visible to other users as an argument.
+static SALT_VARIABLE: &str = "MONGODB_SCHEMA_SALT";
+
+#[derive(Parser, Debug)]
+#[clap(name = "mongodb-schema", version, about)]
+struct Options {
+  /// Files to read. Reads from stdin if none are given, or for `-`.
+  paths: Vec<PathBuf>,
+
+  /// Output format.
+  #[clap(short, long, arg_enum, default_value = "json")]
+  format: Format,
+
+  /// Treat all input as BSON. By default only files ending in `.bson` are.
+  #[clap(long, group = "input")]
+  bson: bool,
+
+  /// Treat all input as newline-delimited JSON.
+  #[clap(long, group = "input")]
+  ndjson: bool,
+
+  /// Treat all input as CSV with a header line. By default only files ending
+  /// in `.csv` are.
+  #[clap(long, group = "input")]
+  csv: bool,
+
+  /// Treat all input as TSV with a header line. By default only files ending
+  /// in `.tsv` are.
+  #[clap(long, group = "input")]
+  tsv: bool,
+
+  /// CSV and TSV header names carry a type, i.e. `age.int32()`, like
+  /// `mongoimport --columnsHaveTypes`.
+  #[clap(long)]
+  columns_have_types: bool,
+
+  /// Leave empty CSV and TSV values out of documents.
+  #[clap(long)]
+  ignore_blanks: bool,
+
+  /// Skip malformed lines, array elements, CSV rows and BSON documents, and
+  /// list them on stderr.
+  #[clap(long)]
+  lenient: bool,
+
+  /// Keep at most this many values of every type; 0 keeps none.
+  #[clap(long)]
+  max_values: Option<usize>,
+
+  /// Don't analyse subdocuments and arrays nested deeper than this. Top
+  /// level fields are at depth 1.
+  #[clap(long, default_value_t = 100)]
+  max_depth: usize,
+
+  /// Analyse at most this many fields of every document and subdocument.
+  #[clap(long)]
+  max_fields: Option<usize>,
+
+  /// Analyse at most this many elements of every array.
+  #[clap(long)]
+  max_array_elements: Option<usize>,
+
+  /// Analyse the fields of a document up to this many bytes of BSON.
+  #[clap(long)]
+  max_document_bytes: Option<usize>,
+
+  /// Fail on documents going past one of the limits, or skip them with
+  /// `--lenient`, instead of analysing them up to the limit.
+  #[clap(long)]
+  limit_errors: bool,
+
+  /// Count arrays without analysing their elements.
+  #[clap(long)]
+  skip_arrays: bool,
+
+  /// Don't compute `unique` and `has_duplicates`.
+  #[clap(long)]
+  skip_unique: bool,
+
+  /// Only analyse fields matching this pattern, i.e. `owner.*` or
+  /// `**.email`. Can be given more than once.
+  #[clap(long, value_name = "PATTERN")]
+  include: Vec<String>,
+
+  /// Don't analyse fields matching this pattern, i.e. `payload.**`. Can be
+  /// given more than once.
+  #[clap(long, value_name = "PATTERN")]
+  exclude: Vec<String>,
+
+  /// Don't keep the values of fields matching this pattern, i.e.
+  /// `**.email`. Can be given more than once.
+  #[clap(long, value_name = "PATTERN")]
+  redact: Vec<String>,
+
+  /// Don't keep the values of fields with names like `email`, `phone` or
+  /// `password`.
+  #[clap(long)]
+  redact_sensitive: bool,
+
+  /// Keep redacted values as hashes, salted with the MONGODB_SCHEMA_SALT
+  /// environment variable, so that they can still be counted.
+  #[clap(long)]
+  hash_redacted: bool,
+
+  /// Read `mongodump --archive` files and print one schema per collection,
+  /// keyed by `db.collection`.
+  #[clap(long, conflicts_with = "input")]
+  archive: bool,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+enum Format {
+  /// The schema as returned by `SchemaParser::into_json`.
+  Json,
+  /// Same as `json`, indented.
+  Pretty,
+  /// The format of the mongodb-schema npm package, as used by Compass.
+  Compass,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Input {
+  Ndjson,
+  Bson,
+  Csv,
+  Tsv,
+}
+
+fn main() {
+  let options = Options::parse();
+  if let Err(e) = run(&options) {
+    eprintln!("mongodb-schema: {}", e);
+    process::exit(1);
+  }
+}
+
+fn run(options: &Options) -> Result<()> {
+  let stdin = Path::new("-");
+  let paths: Vec<&Path> = if options.paths.is_empty() {
+    vec![stdin]
+  } else {
+    options.paths.iter().map(PathBuf::as_path).collect()
+  };
+
+  if options.archive {
+    return run_archive(options, &paths);
+  }
+
+  let mut schema_parser = SchemaParser::with_options(parser_options(options)?)?;
+  schema_parser.set_lenient(options.lenient);
+  for path in paths {
+    let input = input_for(options, path);
+    let name = path.display().to_string();
+    write(&mut schema_parser, open(path)?, input, options, &name)?;
+  }
+  if !schema_parser.ingest_report().is_empty() {
+    eprint!("mongodb-schema: skipped\n{}", schema_parser.ingest_report());
+  }
+
+  let output = match options.format {
+    Format::Json => schema_parser.into_json()?,
+    Format::Pretty => serde_json::to_string_pretty(&schema_parser.flush())?,
+    Format::Compass => schema_parser.into_compass_json()?,
+  };
+  println!("{}", output);
+  Ok(())
+}
+
+fn run_archive(options: &Options, paths: &[&Path]) -> Result<()> {
+  let parser_options = parser_options(options)?;
+  let mut schemas = BTreeMap::new();
+  for path in paths {
+    let name = path.display().to_string();
+    let archive = if options.lenient {
+      SchemaParser::from_archive_lenient(open(path)?, &parser_options)
+    } else {
+      SchemaParser::from_archive_with_options(open(path)?, &parser_options)
+    }
+    .map_err(|e| format!("{}: {}", name, e))?;
+    let mut archive: Vec<(String, SchemaParser)> =
+      archive.into_iter().collect();
+    archive.sort_by(|a, b| a.0.cmp(&b.0));
+    for (namespace, mut schema_parser) in archive {
+      if !schema_parser.ingest_report().is_empty() {
+        eprint!(
+          "mongodb-schema: skipped in {}\n{}",
+          namespace,
+          schema_parser.ingest_report()
+        );
+      }
+      let schema = match options.format {
+        Format::Json | Format::Pretty => {
+          serde_json::to_value(schema_parser.flush())?
+        }
+        Format::Compass => serde_json::to_value(schema_parser.to_compass())?,
+      };
+      if schemas.insert(namespace.to_string(), schema).is_some() {
+        return Err(
+          format!("{}: {} is in more than one archive", name, namespace).into(),
+        );
+      }
+    }
+  }
+
+  let output = match options.format {
+    Format::Pretty => serde_json::to_string_pretty(&schemas)?,
+    Format::Json | Format::Compass => serde_json::to_string(&schemas)?,
+  };
+  println!("{}", output);
+  Ok(())
+}
+
+fn parser_options(options: &Options) -> Result<SchemaParserOptions> {
+  let (redaction, salt) = if options.hash_redacted {
+    let salt = env::var(SALT_VARIABLE).map_err(|_| {
+      format!("--hash-redacted needs {} to be set", SALT_VARIABLE)
+    })?;
+    (Redaction::Hash, salt)
+  } else {
+    (Redaction::Drop, String::new())
+  };
+  Ok(SchemaParserOptions {
+    max_values: options.max_values,
+    max_depth: Some(options.max_depth),
+    max_fields: options.max_fields,
+    max_array_elements: options.max_array_elements,
+    max_document_bytes: options.max_document_bytes,
+    on_limit: if options.limit_errors {
+      OnLimit::Error
+    } else {
+      OnLimit::Truncate
+    },
+    analyse_arrays: !options.skip_arrays,
+    unique: !options.skip_unique,
+    include: options.include.clone(),
+    exclude: options.exclude.clone(),
+    redact: options.redact.clone(),
+    redact_sensitive: options.redact_sensitive,
+    redaction,
+    salt,
+  })
+}
+
+// Readers are buffered, and decompressed if need be, by SchemaParser.
+fn open(path: &Path) -> Result<Box<dyn Read>> {
+  if path == Path::new("-") {
+    return Ok(Box::new(io::stdin()));
+  }
+  let file = File::open(path)
+    .map_err(|e| format!("could not open {}: {}", path.display(), e))?;
+  Ok(Box::new(file))
+}
+
+fn input_for(options: &Options, path: &Path) -> Input {
+  if options.bson {
+    return Input::Bson;
+  }
+  if options.ndjson {
+    return Input::Ndjson;
+  }
+  if options.csv {
+    return Input::Csv;
+  }
+  if options.tsv {
+    return Input::Tsv;
+  }
+  // look past the extension of compressed files, i.e. `cats.bson.gz`
+  let path = match path.extension().and_then(|e| e.to_str()) {
+    Some("gz") | Some("zst") => Path::new(path.file_stem().unwrap_or_default()),
+    _ => path,
+  };
+  match path.extension().and_then(|e| e.to_str()) {
+    Some("bson") => Input::Bson,
+    Some("csv") => Input::Csv,
+    Some("tsv") => Input::Tsv,
+    _ => Input::Ndjson,
+  }
+}
+
+fn write<R: Read>(
+  schema_parser: &mut SchemaParser,
+  reader: R,
+  input: Input,
+  options: &Options,
+  name: &str,
+) -> Result<()> {
+  let csv_options = |delimiter| CsvOptions {
+    delimiter,
+    columns_have_types: options.columns_have_types,
+    ignore_blanks: options.ignore_blanks,
+  };
+  match input {
+    Input::Ndjson => schema_parser.write_json_reader(reader),
+    Input::Bson => schema_parser.write_bson_reader(reader),
+    Input::Csv => schema_parser.write_csv_reader(reader, &csv_options(b',')),
+    Input::Tsv => schema_parser.write_csv_reader(reader, &csv_options(b'\t')),
+  }
+  .map_err(|e| format!("{}: {}", name, e))?;
+  Ok(())
+}
@@ -0,0 +1,279 @@
+use super::{ratio, Bson, Document, Field, FieldType, SchemaParser};
+
+/// Thresholds used by `SchemaParser::check_document_with_options`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CheckOptions {
+  /// Types making up a smaller share of a field than this are reported as
+  /// rare.
+  pub rare_type_threshold: f32,
+  /// Fields present in at least this share of documents are reported when a
+  /// document is missing them.
+  pub required_threshold: f32,
+}
+
+impl Default for CheckOptions {
+  fn default() -> Self {
+    CheckOptions {
+      rare_type_threshold: 0.01,
+      required_threshold: 0.95,
+    }
+  }
+}
+
+/// A way in which a document differs from an inferred schema, as returned by
+/// `SchemaParser::check_document`. Values inside arrays are reported with the
+/// path of the array.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Deviation {
+  /// The path was never seen before.
+  UnknownField { path: String },
+  /// The path was seen, but never with this type.
+  UnseenType { path: String, bson_type: String },
+  /// The path was seen with this type, but only in `probability` of the
+  /// documents that had it.
+  RareType {
+    path: String,
+    bson_type: String,
+    probability: f32,
+  },
+  /// The path is missing, although `probability` of documents have it.
+  MissingField { path: String, probability: f32 },
+}
+
+pub(crate) fn check_document(
+  schema: &SchemaParser,
+  doc: &Document,
+  path: Option<String>,
+  options: &CheckOptions,
+  deviations: &mut Vec<Deviation>,
+) {
+  for (key, value) in doc {
+    let current_path = Field::get_path(key.to_owned(), path.to_owned());
+    match schema.fields.get(key) {
+      Some(field) => {
+        check_value(field, value, &current_path, options, deviations)
+      }
+      None => push(deviations, Deviation::UnknownField { path: current_path }),
+    }
+  }
+
+  let mut missing: Vec<&Field> = schema
+    .fields
+    .iter()
+    .filter(|(key, _)| !doc.contains_key(key))
+    .map(|(_, field)| field)
+    .collect();
+  missing.sort_by(|a, b| a.path.cmp(&b.path));
+  for field in missing {
+    let probability = ratio(field.present_count(), schema.count);
+    if probability >= options.required_threshold {
+      push(
+        deviations,
+        Deviation::MissingField {
+          path: field.path.to_string(),
+          probability,
+        },
+      );
+    }
+  }
+}
+
+fn check_value(
+  field: &Field,
+  value: &Bson,
+  path: &str,
+  options: &CheckOptions,
+  deviations: &mut Vec<Deviation>,
+) {
+  let found = field
+    .types
+    .get(&FieldType::get_type(value))
+    .map(|field_type| (field_type, field.type_count(field_type)))
+    .filter(|(_, count)| *count > 0)
+    .map(|(field_type, count)| {
+      (field_type, ratio(count, field.present_count()))
+    });
+  check_type(found, value, path, options, deviations);
+}
+
+// Values inside an array are compared to the types of all other values that
+// were inside that array.
+fn check_element(
+  array_type: &FieldType,
+  value: &Bson,
+  path: &str,
+  options: &CheckOptions,
+  deviations: &mut Vec<Deviation>,
+) {
+  let total: usize = array_type.types.values().map(|t| t.count).sum();
+  let found = array_type
+    .types
+    .get(&FieldType::get_type(value))
+    .map(|field_type| (field_type, ratio(field_type.count, total)));
+  check_type(found, value, path, options, deviations);
+}
+
+fn check_type(
+  found: Option<(&FieldType, f32)>,
+  value: &Bson,
+  path: &str,
+  options: &CheckOptions,
+  deviations: &mut Vec<Deviation>,
+) {
+  let (field_type, probability) = match found {
+    Some(found) => found,
+    None => {
+      return push(
+        deviations,
+        Deviation::UnseenType {
+          path: path.to_string(),
+          bson_type: FieldType::get_type(value),
+        },
+      )
+    }
+  };
+  if probability < options.rare_type_threshold {
+    push(
+      deviations,
+      Deviation::RareType {
+        path: path.to_string(),
+        bson_type: field_type.bson_type.to_string(),
+        probability,
+      },
+    );
+  }
+
+  match value {
+    Bson::Document(subdoc) => {
+      if let Some(schema) = &field_type.schema {
+        check_document(
+          schema,
+          subdoc,
+          Some(path.to_string()),
+          options,
+          deviations,
+        );
+      }
+    }
+    Bson::Array(arr) => {
+      for val in arr {
+        check_element(field_type, val, path, options, deviations);
+      }
+    }
+    _ => {}
+  }
+}
+
+// arrays can produce the same deviation once per element
+fn push(deviations: &mut Vec<Deviation>, deviation: Deviation) {
+  if !deviations.contains(&deviation) {
+    deviations.push(deviation);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use bson::doc;
+
+  fn schema() -> SchemaParser {
+    let mut schema_parser = SchemaParser::new();
+    for i in 0..200 {
+      schema_parser
+        .write_bson(doc! {
+          "name": "Nori",
+          "age": i,
+          "owner": {"city": "Berlin"},
+          "tags": ["cat", "indoor"],
+        })
+        .unwrap();
+    }
+    schema_parser
+      .write_bson(doc! {"name": "Rey", "age": "two"})
+      .unwrap();
+    schema_parser
+  }
+
+  #[test]
+  fn it_accepts_conforming_documents() {
+    let doc = doc! {
+      "name": "Chashu",
+      "age": 3,
+      "owner": {"city": "Hamburg"},
+      "tags": ["cat"],
+    };
+    assert_eq!(schema().check_document(&doc), vec![]);
+  }
+
+  #[test]
+  fn it_finds_unknown_fields() {
+    let doc = doc! {
+      "name": "Chashu",
+      "age": 3,
+      "owner": {"city": "Hamburg", "country": "DE"},
+      "tags": ["cat"],
+      "colour": "grey",
+    };
+    assert_eq!(
+      schema().check_document(&doc),
+      vec![
+        Deviation::UnknownField {
+          path: "owner.country".to_string()
+        },
+        Deviation::UnknownField {
+          path: "colour".to_string()
+        },
+      ]
+    );
+  }
+
+  #[test]
+  fn it_finds_unseen_and_rare_types() {
+    let doc = doc! {
+      "name": 12,
+      "age": "three",
+      "owner": {"city": "Hamburg"},
+      "tags": ["cat", 1],
+    };
+    let deviations = schema().check_document(&doc);
+    assert_eq!(deviations.len(), 3);
+    assert_eq!(
+      deviations[0],
+      Deviation::UnseenType {
+        path: "name".to_string(),
+        bson_type: "Int32".to_string()
+      }
+    );
+    assert!(matches!(
+      &deviations[1],
+      Deviation::RareType { path, .. } if path == "age"
+    ));
+    assert_eq!(
+      deviations[2],
+      Deviation::UnseenType {
+        path: "tags".to_string(),
+        bson_type: "Int32".to_string()
+      }
+    );
+  }
+
+  #[test]
+  fn it_finds_missing_fields() {
+    let doc = doc! {"age": 3, "owner": {}, "tags": []};
+    let deviations = schema().check_document(&doc);
+    assert_eq!(
+      deviations,
+      vec![
+        Deviation::MissingField {
+          path: "owner.city".to_string(),
+          probability: 1.0
+        },
+        Deviation::MissingField {
+          path: "name".to_string(),
+          probability: 1.0
+        },
+      ]
+    );
+  }
+}
@@ -0,0 +1,283 @@
+use super::{ratio, Field, FieldType, SchemaParser, ValueType};
+use crate::field_type;
+
+/// Schema in the shape produced by the mongodb-js `mongodb-schema` package,
+/// which is what Compass and its ecosystem consume.
+///
+/// Compared to the native output, `fields` and `types` are arrays instead of
+/// maps, keys are named the way `mongodb-schema` names them, and documents
+/// that are missing a field are reported as an `Undefined` type rather than
+/// as `Null`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CompassSchema {
+  pub count: usize,
+  pub fields: Vec<CompassField>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CompassField {
+  pub name: String,
+  pub path: String,
+  pub count: usize,
+  #[serde(rename = "type")]
+  pub field_type: CompassTypeName,
+  pub probability: f32,
+  pub has_duplicates: bool,
+  pub total_count: usize,
+  pub types: Vec<CompassType>,
+}
+
+/// A field's `type` is a single name when the field only ever had one type,
+/// and a list of names otherwise.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum CompassTypeName {
+  Single(String),
+  Multiple(Vec<String>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CompassType {
+  pub name: String,
+  #[serde(rename = "bsonType")]
+  pub bson_type: String,
+  pub path: String,
+  pub count: usize,
+  pub probability: f32,
+  pub unique: usize,
+  pub has_duplicates: bool,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub values: Vec<ValueType>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub lengths: Option<Vec<usize>>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub average_length: Option<f32>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub total_count: Option<usize>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub fields: Vec<CompassField>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub types: Vec<CompassType>,
+}
+
+pub static UNDEFINED: &str = "Undefined";
+
+/// Maps this crate's BSON type names to the ones `mongodb-schema` uses.
+pub fn type_name(bson_type: &str) -> &str {
+  match bson_type {
+    "UtcDatetime" => "Date",
+    "BinData" => "Binary",
+    "Regex" => "BSONRegExp",
+    "Symbol" => "BSONSymbol",
+    "JavaScriptCode" | "JavaScriptCodeWithScope" => "Code",
+    "DbPointer" => "DBPointer",
+    _ => bson_type,
+  }
+}
+
+impl From<&SchemaParser> for CompassSchema {
+  fn from(schema: &SchemaParser) -> Self {
+    CompassSchema {
+      count: schema.count,
+      fields: CompassField::from_schema(schema),
+    }
+  }
+}
+
+impl CompassField {
+  fn from_schema(schema: &SchemaParser) -> Vec<CompassField> {
+    let mut fields: Vec<CompassField> = schema
+      .fields
+      .values()
+      .map(|field| CompassField::from_field(field, schema.count))
+      .collect();
+    fields.sort_by(|a, b| {
+      a.name
+        .to_lowercase()
+        .cmp(&b.name.to_lowercase())
+        .then_with(|| a.name.cmp(&b.name))
+    });
+    fields
+  }
+
+  fn from_field(field: &Field, parent_count: usize) -> Self {
+    let present = field.present_count();
+    let total_count = field.count;
+
+    let mut types: Vec<CompassType> = field
+      .types
+      .values()
+      .filter_map(|field_type| {
+        let count = field.type_count(field_type);
+        if count == 0 {
+          return None;
+        }
+        Some(CompassType::from_type(field_type, count, total_count))
+      })
+      .collect();
+    if field.missing > 0 {
+      types.push(CompassType::undefined(
+        &field.path,
+        field.missing,
+        total_count,
+      ));
+    }
+    sort_types(&mut types);
+
+    let names: Vec<String> = types.iter().map(|t| t.name.to_string()).collect();
+    let field_type = if names.len() == 1 {
+      CompassTypeName::Single(names[0].to_string())
+    } else {
+      CompassTypeName::Multiple(names)
+    };
+
+    CompassField {
+      name: field.name.to_string(),
+      path: field.path.to_string(),
+      count: present,
+      field_type,
+      probability: ratio(present, parent_count),
+      has_duplicates: types.iter().any(|t| t.has_duplicates),
+      total_count,
+      types,
+    }
+  }
+}
+
+impl CompassType {
+  fn from_type(
+    field_type: &FieldType,
+    count: usize,
+    parent_total: usize,
+  ) -> Self {
+    let name = type_name(&field_type.bson_type);
+    let mut compass_type = CompassType {
+      name: name.to_string(),
+      bson_type: name.to_string(),
+      path: field_type.path.to_string(),
+      count,
+      probability: ratio(count, parent_total),
+      unique: field_type.unique.unwrap_or(0),
+      has_duplicates: field_type.has_duplicates,
+      values: Vec::new(),
+      lengths: None,
+      average_length: None,
+      total_count: None,
+      fields: Vec::new(),
+      types: Vec::new(),
+    };
+
+    if field_type.bson_type == field_type::DOCUMENT {
+      if let Some(schema) = &field_type.schema {
+        compass_type.fields = CompassField::from_schema(schema);
+      }
+    } else if field_type.bson_type == field_type::ARRAY {
+      let total_count: usize = field_type.lengths.iter().sum();
+      let mut types: Vec<CompassType> = field_type
+        .types
+        .values()
+        .map(|child| CompassType::from_type(child, child.count, total_count))
+        .collect();
+      sort_types(&mut types);
+      compass_type.types = types;
+      // mongodb-schema keeps values for the elements, not for the arrays.
+      compass_type.unique = 0;
+      compass_type.has_duplicates = false;
+      compass_type.average_length = Some(ratio(total_count, count));
+      compass_type.total_count = Some(total_count);
+      compass_type.lengths = Some(field_type.lengths.clone());
+    } else if field_type.bson_type == field_type::NULL {
+      // mongodb-schema does not keep values for nulls, but still counts them.
+      compass_type.unique = 1;
+      compass_type.has_duplicates = count > 1;
+    } else {
+      compass_type.values = field_type.values.clone();
+    }
+
+    compass_type
+  }
+
+  fn undefined(path: &str, count: usize, parent_total: usize) -> Self {
+    CompassType {
+      name: UNDEFINED.to_string(),
+      bson_type: UNDEFINED.to_string(),
+      path: path.to_string(),
+      count,
+      probability: ratio(count, parent_total),
+      unique: 0,
+      has_duplicates: false,
+      values: Vec::new(),
+      lengths: None,
+      average_length: None,
+      total_count: None,
+      fields: Vec::new(),
+      types: Vec::new(),
+    }
+  }
+}
+
+// mongodb-schema lists the most likely type first.
+fn sort_types(types: &mut [CompassType]) {
+  types.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use bson::doc;
+
+  #[test]
+  fn it_maps_type_names() {
+    assert_eq!(type_name("UtcDatetime"), "Date");
+    assert_eq!(type_name("BinData"), "Binary");
+    assert_eq!(type_name("String"), "String");
+  }
+
+  #[allow(clippy::float_cmp)]
+  #[test]
+  fn it_reports_missing_as_undefined() {
+    let mut schema_parser = SchemaParser::new();
+    schema_parser
+      .write_bson(doc! {"name": "Nori", "type": "Cat"})
+      .unwrap();
+    schema_parser.write_bson(doc! {"name": "Rey"}).unwrap();
+    let compass = schema_parser.to_compass();
+    let field = compass.fields.iter().find(|f| f.name == "type").unwrap();
+    assert_eq!(field.count, 1);
+    assert_eq!(field.probability, 0.5);
+    assert_eq!(
+      field.field_type,
+      CompassTypeName::Multiple(vec![
+        "String".to_string(),
+        "Undefined".to_string()
+      ])
+    );
+  }
+
+  #[test]
+  fn it_keeps_explicit_nulls_apart_from_missing() {
+    let mut schema_parser = SchemaParser::new();
+    schema_parser
+      .write_bson(doc! {"type": bson::Bson::Null})
+      .unwrap();
+    schema_parser.write_bson(doc! {"name": "Rey"}).unwrap();
+    let compass = schema_parser.to_compass();
+    let field = compass.fields.iter().find(|f| f.name == "type").unwrap();
+    let null = field.types.iter().find(|t| t.name == "Null").unwrap();
+    let undefined = field.types.iter().find(|t| t.name == "Undefined").unwrap();
+    assert_eq!(null.count, 1);
+    assert_eq!(undefined.count, 1);
+  }
+
+  #[test]
+  fn it_sorts_fields_by_name() {
+    let mut schema_parser = SchemaParser::new();
+    schema_parser
+      .write_bson(doc! {"b": 1, "C": 2, "a": 3})
+      .unwrap();
+    let compass = schema_parser.to_compass();
+    let names: Vec<&str> =
+      compass.fields.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, vec!["a", "b", "C"]);
+  }
+}
@@ -0,0 +1,698 @@
+use super::{Bson, Document, Error, ErrorKind, Location, Result, SchemaParser};
+use crate::reader::MAX_BSON_DEPTH;
+use serde_json::json;
+use std::convert::TryFrom;
+use std::io::Read;
+
+/// How `SchemaParser::write_csv_reader` reads its input. Mirrors the
+/// `mongoimport` flags of the same name.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CsvOptions {
+  /// Field separator, `b','` for CSV or `b'\t'` for TSV.
+  pub delimiter: u8,
+  /// Header names carry a type, i.e. `age.int32()` or
+  /// `born.date(2006-01-02)`. Otherwise numbers are detected and everything
+  /// else is a string.
+  pub columns_have_types: bool,
+  /// Leave empty values out of documents instead of writing empty strings.
+  pub ignore_blanks: bool,
+}
+
+impl Default for CsvOptions {
+  fn default() -> Self {
+    CsvOptions {
+      delimiter: b',',
+      columns_have_types: false,
+      ignore_blanks: false,
+    }
+  }
+}
+
+// Column types of `mongoimport --columnsHaveTypes`.
+#[derive(Debug, Clone, PartialEq)]
+enum ColumnType {
+  Auto,
+  String,
+  Int32,
+  Int64,
+  Double,
+  Decimal,
+  Boolean,
+  Date(Vec<DateToken>),
+  Binary(Encoding),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Encoding {
+  Base32,
+  Base64,
+  Hex,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Column {
+  // dotted names become nested documents
+  path: Vec<String>,
+  column_type: ColumnType,
+}
+
+impl Column {
+  fn parse(header: &str, has_type: bool) -> Result<Self> {
+    if !has_type {
+      return Ok(Column {
+        path: split_path(header)?,
+        column_type: ColumnType::Auto,
+      });
+    }
+
+    // `name.type(argument)`; the name itself can contain dots
+    let open = match header.find('(') {
+      Some(open) if header.ends_with(')') => open,
+      _ => return Err(no_type(header)),
+    };
+    let dot = header[..open].rfind('.').ok_or_else(|| no_type(header))?;
+    let name = &header[..dot];
+    let argument = &header[open + 1..header.len() - 1];
+    let column_type = match (&header[dot + 1..open], argument) {
+      ("auto", "") => ColumnType::Auto,
+      ("string", "") => ColumnType::String,
+      ("int32", "") => ColumnType::Int32,
+      ("int64", "") => ColumnType::Int64,
+      ("double", "") => ColumnType::Double,
+      ("decimal", "") => ColumnType::Decimal,
+      ("boolean", "") => ColumnType::Boolean,
+      ("date", layout) | ("date_go", layout) => {
+        ColumnType::Date(tokenize(layout, GO_LAYOUT))
+      }
+      ("date_ms", layout) => ColumnType::Date(tokenize(layout, MS_LAYOUT)),
+      ("date_oracle", layout) => {
+        ColumnType::Date(tokenize(layout, ORACLE_LAYOUT))
+      }
+      ("binary", "base32") => ColumnType::Binary(Encoding::Base32),
+      ("binary", "base64") => ColumnType::Binary(Encoding::Base64),
+      ("binary", "hex") => ColumnType::Binary(Encoding::Hex),
+      (column_type, _) => {
+        let message =
+          format!("column '{}' has an unknown type '{}'", header, column_type);
+        return Err(ErrorKind::InvalidInput(message).into());
+      }
+    };
+    Ok(Column {
+      path: split_path(name)?,
+      column_type,
+    })
+  }
+
+  fn value(&self, value: &str) -> Result<Bson> {
+    let invalid = || {
+      let message = format!("'{}' is not a valid {}", value, self.kind());
+      ErrorKind::InvalidValue(message)
+    };
+    Ok(match &self.column_type {
+      ColumnType::Auto => auto(value),
+      ColumnType::String => Bson::String(value.to_string()),
+      ColumnType::Int32 => Bson::Int32(value.parse().map_err(|_| invalid())?),
+      ColumnType::Int64 => Bson::Int64(value.parse().map_err(|_| invalid())?),
+      ColumnType::Double => Bson::Double(value.parse().map_err(|_| invalid())?),
+      ColumnType::Decimal => Bson::try_from(json!({ "$numberDecimal": value }))
+        .map_err(|_| invalid())?,
+      ColumnType::Boolean => match value.to_lowercase().as_str() {
+        "true" | "1" => Bson::Boolean(true),
+        "false" | "0" => Bson::Boolean(false),
+        _ => return Err(invalid().into()),
+      },
+      ColumnType::Date(layout) => {
+        let millis = parse_date(value, layout).ok_or_else(invalid)?;
+        Bson::DateTime(bson::DateTime::from_millis(millis))
+      }
+      ColumnType::Binary(encoding) => {
+        let base64 = match encoding {
+          Encoding::Base64 => value.to_string(),
+          Encoding::Hex => to_base64(&from_hex(value).ok_or_else(invalid)?),
+          Encoding::Base32 => {
+            to_base64(&from_base32(value).ok_or_else(invalid)?)
+          }
+        };
+        Bson::try_from(json!({
+          "$binary": { "base64": base64, "subType": "00" }
+        }))
+        .map_err(|_| invalid())?
+      }
+    })
+  }
+
+  fn kind(&self) -> &str {
+    match self.column_type {
+      ColumnType::Auto => "auto",
+      ColumnType::String => "string",
+      ColumnType::Int32 => "int32",
+      ColumnType::Int64 => "int64",
+      ColumnType::Double => "double",
+      ColumnType::Decimal => "decimal",
+      ColumnType::Boolean => "boolean",
+      ColumnType::Date(_) => "date",
+      ColumnType::Binary(_) => "binary",
+    }
+  }
+}
+
+pub(crate) fn write_csv<R: Read>(
+  schema_parser: &mut SchemaParser,
+  reader: R,
+  options: &CsvOptions,
+) -> Result<usize> {
+  let mut reader = csv::ReaderBuilder::new()
+    .delimiter(options.delimiter)
+    .flexible(true)
+    .from_reader(reader);
+  let columns = reader
+    .headers()?
+    .iter()
+    .map(|header| Column::parse(header, options.columns_have_types))
+    .collect::<Result<Vec<Column>>>()?;
+
+  let mut count = 0;
+  for record in reader.records() {
+    let record = match record {
+      Ok(record) => record,
+      // a malformed record, i.e. invalid UTF-8, can still be skipped
+      Err(e) if !e.is_io_error() => {
+        let line = e.position().map_or(0, |position| position.line());
+        schema_parser.skip(Error::from(e).at(Location::Line(line)), "")?;
+        continue;
+      }
+      Err(e) => return Err(e.into()),
+    };
+    let line = record.position().map_or(0, |position| position.line());
+    let written = record_to_document(&record, &columns, options)
+      .and_then(|doc| schema_parser.write_bson(doc))
+      .map_err(|e| e.at(Location::Line(line)));
+    match written {
+      Ok(()) => count += 1,
+      Err(e) => {
+        let snippet = record.iter().collect::<Vec<&str>>().join(",");
+        schema_parser.skip(e, &snippet)?;
+      }
+    }
+  }
+  Ok(count)
+}
+
+fn record_to_document(
+  record: &csv::StringRecord,
+  columns: &[Column],
+  options: &CsvOptions,
+) -> Result<Document> {
+  let mut doc = Document::new();
+  for (column, value) in columns.iter().zip(record.iter()) {
+    if value.is_empty() && options.ignore_blanks {
+      continue;
+    }
+    let value = column
+      .value(value)
+      .map_err(|e| e.in_field(column.path.join(".")))?;
+    insert(&mut doc, &column.path, value)?;
+  }
+  Ok(doc)
+}
+
+// Same as `mongoimport` without column types: integers that fit are Int32,
+// then Long, then Double, and anything else is a String.
+fn auto(value: &str) -> Bson {
+  if let Ok(num) = value.parse::<i64>() {
+    return match i32::try_from(num) {
+      Ok(num) => Bson::Int32(num),
+      Err(_) => Bson::Int64(num),
+    };
+  }
+  match value.parse::<f64>() {
+    Ok(num) => Bson::Double(num),
+    Err(_) => Bson::String(value.to_string()),
+  }
+}
+
+// Every segment of a dotted name is a level of nesting, so names are held to
+// the depth BSON input may have.
+fn split_path(name: &str) -> Result<Vec<String>> {
+  let path: Vec<String> = name.split('.').map(str::to_string).collect();
+  if path.iter().any(String::is_empty) {
+    let message = format!("column '{}' has an empty field name", name);
+    return Err(ErrorKind::InvalidInput(message).into());
+  }
+  if path.len() > MAX_BSON_DEPTH {
+    let name: String = name.chars().take(32).collect();
+    let message = format!(
+      "column '{}...' is nested deeper than {}",
+      name, MAX_BSON_DEPTH
+    );
+    return Err(ErrorKind::InvalidInput(message).into());
+  }
+  Ok(path)
+}
+
+fn no_type(header: &str) -> Error {
+  let message = format!("column '{}' has no type", header);
+  ErrorKind::InvalidInput(message).into()
+}
+
+fn insert(doc: &mut Document, path: &[String], value: Bson) -> Result<()> {
+  let (key, rest) = match path.split_first() {
+    Some(split) => split,
+    None => return Ok(()),
+  };
+  if rest.is_empty() {
+    doc.insert(key.to_string(), value);
+    return Ok(());
+  }
+  let entry = doc
+    .entry(key.to_string())
+    .or_insert_with(|| Bson::Document(Document::new()));
+  match entry {
+    Bson::Document(subdoc) => insert(subdoc, rest, value),
+    _ => {
+      let message = format!("'{}' is both a value and a document", key);
+      Err(ErrorKind::InvalidInput(message).into())
+    }
+  }
+}
+
+// Parts of a date layout. Numbers take up to their width in digits.
+#[derive(Debug, Clone, PartialEq)]
+enum DateToken {
+  Year,
+  Month,
+  MonthName,
+  Day,
+  Hour,
+  Minute,
+  Second,
+  Fraction,
+  Offset,
+  Literal(char),
+}
+
+// Go's reference time, `Mon Jan 2 15:04:05 MST 2006`.
+static GO_LAYOUT: &[(&str, DateToken)] = &[
+  ("Z07:00", DateToken::Offset),
+  ("-07:00", DateToken::Offset),
+  ("-0700", DateToken::Offset),
+  ("2006", DateToken::Year),
+  ("Jan", DateToken::MonthName),
+  ("000", DateToken::Fraction),
+  ("999", DateToken::Fraction),
+  ("01", DateToken::Month),
+  ("02", DateToken::Day),
+  ("15", DateToken::Hour),
+  ("04", DateToken::Minute),
+  ("05", DateToken::Second),
+  ("1", DateToken::Month),
+  ("2", DateToken::Day),
+  ("4", DateToken::Minute),
+  ("5", DateToken::Second),
+];
+
+// .NET custom date and time format strings.
+static MS_LAYOUT: &[(&str, DateToken)] = &[
+  ("yyyy", DateToken::Year),
+  ("MMM", DateToken::MonthName),
+  ("fff", DateToken::Fraction),
+  ("zzz", DateToken::Offset),
+  ("MM", DateToken::Month),
+  ("dd", DateToken::Day),
+  ("HH", DateToken::Hour),
+  ("mm", DateToken::Minute),
+  ("ss", DateToken::Second),
+  ("M", DateToken::Month),
+  ("d", DateToken::Day),
+  ("H", DateToken::Hour),
+  ("m", DateToken::Minute),
+  ("s", DateToken::Second),
+];
+
+// Oracle datetime format models.
+static ORACLE_LAYOUT: &[(&str, DateToken)] = &[
+  ("TZH:TZM", DateToken::Offset),
+  ("YYYY", DateToken::Year),
+  ("HH24", DateToken::Hour),
+  ("MON", DateToken::MonthName),
+  ("FF3", DateToken::Fraction),
+  ("FF", DateToken::Fraction),
+  ("MM", DateToken::Month),
+  ("DD", DateToken::Day),
+  ("MI", DateToken::Minute),
+  ("SS", DateToken::Second),
+];
+
+static MONTHS: [&str; 12] = [
+  "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov",
+  "dec",
+];
+
+// Patterns are listed longest first, so the first match wins.
+fn tokenize(layout: &str, patterns: &[(&str, DateToken)]) -> Vec<DateToken> {
+  let mut tokens = Vec::new();
+  let mut rest = layout;
+  while let Some(c) = rest.chars().next() {
+    match patterns
+      .iter()
+      .find(|(pattern, _)| rest.starts_with(pattern))
+    {
+      Some((pattern, token)) => {
+        tokens.push(token.clone());
+        rest = &rest[pattern.len()..];
+      }
+      None => {
+        tokens.push(DateToken::Literal(c));
+        rest = &rest[c.len_utf8()..];
+      }
+    }
+  }
+  tokens
+}
+
+// Milliseconds since the unix epoch, or `None` if `value` doesn't match the
+// layout.
+fn parse_date(value: &str, layout: &[DateToken]) -> Option<i64> {
+  let (mut year, mut month, mut day) = (1970, 1, 1);
+  let (mut hour, mut minute, mut second, mut millis) = (0, 0, 0, 0);
+  let mut offset = 0;
+  let mut rest = value;
+
+  for token in layout {
+    match token {
+      DateToken::Year => year = number(&mut rest, 4)?,
+      DateToken::Month => month = number(&mut rest, 2)?,
+      DateToken::Day => day = number(&mut rest, 2)?,
+      DateToken::Hour => hour = number(&mut rest, 2)?,
+      DateToken::Minute => minute = number(&mut rest, 2)?,
+      DateToken::Second => second = number(&mut rest, 2)?,
+      DateToken::MonthName => {
+        let name = rest.get(..3)?.to_lowercase();
+        month = MONTHS.iter().position(|m| *m == name)? as i64 + 1;
+        rest = &rest[3..];
+      }
+      DateToken::Fraction => {
+        let digits = rest.chars().take_while(char::is_ascii_digit).count();
+        if digits == 0 {
+          return None;
+        }
+        // only milliseconds are kept
+        let fraction = format!("{:0<3}", &rest[..digits.min(3)]);
+        millis = fraction.parse().ok()?;
+        rest = &rest[digits..];
+      }
+      DateToken::Offset => {
+        if let Some(stripped) = rest.strip_prefix('Z') {
+          rest = stripped;
+          continue;
+        }
+        let sign = match rest.chars().next()? {
+          '+' => 1,
+          '-' => -1,
+          _ => return None,
+        };
+        rest = &rest[1..];
+        let hours = number(&mut rest, 2)?;
+        if let Some(stripped) = rest.strip_prefix(':') {
+          rest = stripped;
+        }
+        let minutes = number(&mut rest, 2)?;
+        offset = sign * (hours * 60 + minutes) * 60 * 1000;
+      }
+      DateToken::Literal(c) => rest = rest.strip_prefix(*c)?,
+    }
+  }
+
+  if !rest.is_empty()
+    || !(1..=12).contains(&month)
+    || !(1..=31).contains(&day)
+    || hour > 23
+    || minute > 59
+    || second > 60
+  {
+    return None;
+  }
+  let days = days_from_civil(year, month, day);
+  let seconds = days * 86400 + hour * 3600 + minute * 60 + second;
+  Some(seconds * 1000 + millis - offset)
+}
+
+// Takes at most `width` digits off the front of `rest`.
+fn number(rest: &mut &str, width: usize) -> Option<i64> {
+  let digits = rest
+    .chars()
+    .take(width)
+    .take_while(char::is_ascii_digit)
+    .count();
+  if digits == 0 {
+    return None;
+  }
+  let num = rest[..digits].parse().ok()?;
+  *rest = &rest[digits..];
+  Some(num)
+}
+
+// Days since 1970-01-01 of a date in the proleptic Gregorian calendar.
+// http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+  let year = if month <= 2 { year - 1 } else { year };
+  let era = (if year >= 0 { year } else { year - 399 }) / 400;
+  let year_of_era = year - era * 400;
+  let month = (month + 9) % 12;
+  let day_of_year = (153 * month + 2) / 5 + day - 1;
+  let day_of_era =
+    year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+  era * 146_097 + day_of_era - 719_468
+}
+
+// `usize::is_multiple_of` needs Rust 1.87
+#[allow(clippy::manual_is_multiple_of)]
+fn from_hex(value: &str) -> Option<Vec<u8>> {
+  if value.len() % 2 != 0 {
+    return None;
+  }
+  (0..value.len())
+    .step_by(2)
+    .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+    .collect()
+}
+
+fn from_base32(value: &str) -> Option<Vec<u8>> {
+  static ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+  let mut bytes = Vec::new();
+  let (mut buffer, mut bits) = (0u64, 0);
+  for c in value.trim_end_matches('=').bytes() {
+    let index = ALPHABET.iter().position(|a| *a == c)? as u64;
+    buffer = (buffer << 5) | index;
+    bits += 5;
+    if bits >= 8 {
+      bits -= 8;
+      bytes.push((buffer >> bits) as u8);
+    }
+  }
+  Some(bytes)
+}
+
+fn to_base64(bytes: &[u8]) -> String {
+  static ALPHABET: &[u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+  let mut encoded = String::new();
+  for chunk in bytes.chunks(3) {
+    let buffer = chunk.iter().enumerate().fold(0u32, |buffer, (i, byte)| {
+      buffer | u32::from(*byte) << (16 - i * 8)
+    });
+    for i in 0..4 {
+      if i <= chunk.len() {
+        let index = (buffer >> (18 - i * 6)) & 0x3f;
+        encoded.push(ALPHABET[index as usize] as char);
+      } else {
+        encoded.push('=');
+      }
+    }
+  }
+  encoded
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::IngestErrorKind;
+  use bson::doc;
+
+  fn write(csv: &str, options: &CsvOptions) -> SchemaParser {
+    let mut schema_parser = SchemaParser::new();
+    write_csv(&mut schema_parser, csv.as_bytes(), options).unwrap();
+    schema_parser
+  }
+
+  fn types(schema_parser: &SchemaParser, path: &str) -> Vec<String> {
+    let mut types: Vec<String> =
+      schema_parser.fields[path].types.keys().cloned().collect();
+    types.sort();
+    types
+  }
+
+  #[test]
+  fn it_detects_numbers() {
+    let csv = "name,age,weight,phone\nNori,4,4.5,491234568789\n";
+    let schema_parser = write(csv, &CsvOptions::default());
+    assert_eq!(schema_parser.count, 1);
+    assert_eq!(types(&schema_parser, "name"), vec!["String"]);
+    assert_eq!(types(&schema_parser, "age"), vec!["Int32"]);
+    assert_eq!(types(&schema_parser, "weight"), vec!["Double"]);
+    assert_eq!(types(&schema_parser, "phone"), vec!["Long"]);
+  }
+
+  #[test]
+  fn it_reads_tsv_and_blanks() {
+    let tsv = "name\tage\nNori\t\n";
+    let options = CsvOptions {
+      delimiter: b'\t',
+      ..CsvOptions::default()
+    };
+    assert_eq!(types(&write(tsv, &options), "age"), vec!["String"]);
+    let options = CsvOptions {
+      delimiter: b'\t',
+      ignore_blanks: true,
+      ..CsvOptions::default()
+    };
+    assert!(!write(tsv, &options).fields.contains_key("age"));
+  }
+
+  #[test]
+  fn it_nests_dotted_names() {
+    let csv = "name,owner.name,owner.address.city\nNori,Ada,Berlin\n";
+    let schema_parser = write(csv, &CsvOptions::default());
+    assert_eq!(types(&schema_parser, "owner"), vec!["Document"]);
+    let owner = schema_parser.fields["owner"].types["Document"]
+      .schema
+      .as_ref()
+      .unwrap();
+    assert_eq!(types(owner, "address"), vec!["Document"]);
+  }
+
+  #[test]
+  fn it_uses_column_types() {
+    let csv = "\
+name.string(),age.int64(),indoor.boolean(),born.date(2006-01-02),price.decimal(),chip.binary(hex)
+4,4,true,2019-09-03,9.99,0aff
+";
+    let options = CsvOptions {
+      columns_have_types: true,
+      ..CsvOptions::default()
+    };
+    let schema_parser = write(csv, &options);
+    assert_eq!(types(&schema_parser, "name"), vec!["String"]);
+    assert_eq!(types(&schema_parser, "age"), vec!["Long"]);
+    assert_eq!(types(&schema_parser, "indoor"), vec!["Boolean"]);
+    assert_eq!(types(&schema_parser, "born"), vec!["UtcDatetime"]);
+    assert_eq!(types(&schema_parser, "price"), vec!["Decimal128"]);
+    assert_eq!(types(&schema_parser, "chip"), vec!["BinData"]);
+  }
+
+  #[test]
+  fn it_reports_invalid_values() {
+    let csv = "name.string(),age.int32()\nNori,4\nRey,two\n";
+    let options = CsvOptions {
+      columns_have_types: true,
+      ..CsvOptions::default()
+    };
+    let mut schema_parser = SchemaParser::new();
+    let error =
+      write_csv(&mut schema_parser, csv.as_bytes(), &options).unwrap_err();
+    assert_eq!(
+      error.to_string(),
+      "line 3, field 'age': 'two' is not a valid int32"
+    );
+    assert_eq!(schema_parser.count, 1);
+  }
+
+  #[test]
+  fn it_skips_invalid_rows() {
+    let csv = "name.string(),age.int32()\nNori,4\nRey,two\nChashu,3\n";
+    let options = CsvOptions {
+      columns_have_types: true,
+      ..CsvOptions::default()
+    };
+    let mut schema_parser = SchemaParser::new();
+    schema_parser.set_lenient(true);
+    let count =
+      write_csv(&mut schema_parser, csv.as_bytes(), &options).unwrap();
+    assert_eq!(count, 2);
+    let error = &schema_parser.ingest_report().errors[0];
+    assert_eq!(error.location, Location::Line(3));
+    assert_eq!(error.kind, IngestErrorKind::InvalidValue);
+    assert_eq!(error.snippet, "Rey,two");
+  }
+
+  #[test]
+  fn it_rejects_untyped_columns() {
+    assert!(Column::parse("name", true).is_err());
+    assert!(Column::parse("name.text()", true).is_err());
+    assert_eq!(
+      Column::parse("owner.name.string()", true).unwrap().path,
+      vec!["owner", "name"]
+    );
+  }
+
+  #[test]
+  fn it_rejects_headers_nested_too_deeply() {
+    let options = CsvOptions::default();
+    let header = vec!["a"; MAX_BSON_DEPTH].join(".");
+    let schema_parser = write(&format!("{}\n1\n", header), &options);
+    assert_eq!(schema_parser.count, 1);
+
+    // deep enough to overflow the stack if it were written
+    let header = vec!["a"; 20_000].join(".");
+    let mut schema_parser = SchemaParser::new();
+    let csv = format!("{}\n1\n", header);
+    let error =
+      write_csv(&mut schema_parser, csv.as_bytes(), &options).unwrap_err();
+    assert!(error.to_string().contains("nested deeper than 100"));
+  }
+
+  #[test]
+  fn it_parses_date_layouts() {
+    let go = tokenize("2006-01-02T15:04:05.000Z07:00", GO_LAYOUT);
+    assert_eq!(
+      parse_date("2019-09-03T09:24:58.120+02:00", &go),
+      Some(1_567_495_498_120)
+    );
+    assert_eq!(
+      parse_date("2019-09-03T09:24:58.12Z", &go),
+      Some(1_567_502_698_120)
+    );
+    let ms = tokenize("dd/MM/yyyy HH:mm", MS_LAYOUT);
+    assert_eq!(parse_date("03/09/2019 09:24", &ms), Some(1_567_502_640_000));
+    let oracle = tokenize("DD-MON-YYYY", ORACLE_LAYOUT);
+    assert_eq!(parse_date("03-SEP-2019", &oracle), Some(1_567_468_800_000));
+    assert_eq!(
+      parse_date("1969-12-31", &tokenize("2006-01-02", GO_LAYOUT)),
+      Some(-86_400_000)
+    );
+    assert_eq!(
+      parse_date("2019-13-03", &tokenize("2006-01-02", GO_LAYOUT)),
+      None
+    );
+    assert_eq!(
+      parse_date("2019-09-03x", &tokenize("2006-01-02", GO_LAYOUT)),
+      None
+    );
+  }
+
+  #[test]
+  fn it_converts_binary_encodings() {
+    assert_eq!(from_hex("0aff"), Some(vec![0x0a, 0xff]));
+    assert_eq!(from_hex("0a0"), None);
+    assert_eq!(from_base32("MZXW6YQ="), Some(b"foob".to_vec()));
+    assert_eq!(to_base64(b"foob"), "Zm9vYg==");
+    assert_eq!(to_base64(b"foo"), "Zm9v");
+  }
+
+  #[test]
+  fn it_rejects_conflicting_columns() {
+    let mut doc = doc! {"owner": "Ada"};
+    let path = vec!["owner".to_string(), "name".to_string()];
+    assert!(insert(&mut doc, &path, Bson::Null).is_err());
+  }
+}
@@ -0,0 +1,277 @@
+use super::{ratio, Result, SchemaParser};
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+
+/// Probability changes smaller than this are left out of a `SchemaDiff`.
+pub static DEFAULT_PROBABILITY_THRESHOLD: f32 = 0.05;
+
+/// Structural differences between two schemas, as returned by
+/// `SchemaParser::diff`. Paths are dotted, i.e. `address.postal_code`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct SchemaDiff {
+  /// Paths that only exist in the other schema.
+  pub added: Vec<String>,
+  /// Paths that only exist in this schema.
+  pub removed: Vec<String>,
+  /// Paths present in both schemas whose set of BSON types changed.
+  pub type_changes: Vec<TypeChange>,
+  /// Paths present in both schemas whose probability, or the share of one of
+  /// their types, moved by more than the threshold.
+  pub probability_changes: Vec<ProbabilityChange>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TypeChange {
+  pub path: String,
+  pub added: Vec<String>,
+  pub removed: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProbabilityChange {
+  pub path: String,
+  /// `None` when the probability of the field itself changed, otherwise the
+  /// type whose share of the field changed.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub bson_type: Option<String>,
+  pub before: f32,
+  pub after: f32,
+}
+
+// What a single path looks like in one schema, with documents it was missing
+// from left out.
+#[derive(Debug, Default)]
+struct PathSummary {
+  present: usize,
+  parent_count: usize,
+  types: BTreeMap<String, usize>,
+}
+
+impl PathSummary {
+  fn probability(&self) -> f32 {
+    ratio(self.present, self.parent_count)
+  }
+
+  fn type_probability(&self, bson_type: &str) -> f32 {
+    let count = self.types.get(bson_type).cloned().unwrap_or(0);
+    ratio(count, self.present)
+  }
+}
+
+impl SchemaDiff {
+  /// Returns `true` if both schemas have the same shape.
+  pub fn is_empty(&self) -> bool {
+    self.added.is_empty()
+      && self.removed.is_empty()
+      && self.type_changes.is_empty()
+      && self.probability_changes.is_empty()
+  }
+
+  /// Returns the diff as a serde_json string.
+  pub fn to_json(&self) -> Result<String> {
+    Ok(serde_json::to_string(self)?)
+  }
+
+  pub(crate) fn new(
+    before: &SchemaParser,
+    after: &SchemaParser,
+    threshold: f32,
+  ) -> Self {
+    let before = summarise(before);
+    let after = summarise(after);
+    let mut diff = SchemaDiff::default();
+
+    for (path, summary) in &before {
+      let other = match after.get(path) {
+        Some(other) => other,
+        None => {
+          diff.removed.push(path.to_string());
+          continue;
+        }
+      };
+
+      let added: Vec<String> = other
+        .types
+        .keys()
+        .filter(|t| !summary.types.contains_key(*t))
+        .cloned()
+        .collect();
+      let removed: Vec<String> = summary
+        .types
+        .keys()
+        .filter(|t| !other.types.contains_key(*t))
+        .cloned()
+        .collect();
+      if !added.is_empty() || !removed.is_empty() {
+        diff.type_changes.push(TypeChange {
+          path: path.to_string(),
+          added,
+          removed,
+        });
+      }
+
+      let (from, to) = (summary.probability(), other.probability());
+      if (to - from).abs() > threshold {
+        diff.probability_changes.push(ProbabilityChange {
+          path: path.to_string(),
+          bson_type: None,
+          before: from,
+          after: to,
+        });
+      }
+      for bson_type in summary.types.keys() {
+        if !other.types.contains_key(bson_type) {
+          continue;
+        }
+        let from = summary.type_probability(bson_type);
+        let to = other.type_probability(bson_type);
+        if (to - from).abs() > threshold {
+          diff.probability_changes.push(ProbabilityChange {
+            path: path.to_string(),
+            bson_type: Some(bson_type.to_string()),
+            before: from,
+            after: to,
+          });
+        }
+      }
+    }
+
+    for path in after.keys() {
+      if !before.contains_key(path) {
+        diff.added.push(path.to_string());
+      }
+    }
+
+    diff
+  }
+}
+
+impl Display for SchemaDiff {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.is_empty() {
+      return writeln!(f, "no changes");
+    }
+    for path in &self.added {
+      writeln!(f, "+ {}", path)?;
+    }
+    for path in &self.removed {
+      writeln!(f, "- {}", path)?;
+    }
+    for change in &self.type_changes {
+      write!(f, "~ {}:", change.path)?;
+      for bson_type in &change.added {
+        write!(f, " +{}", bson_type)?;
+      }
+      for bson_type in &change.removed {
+        write!(f, " -{}", bson_type)?;
+      }
+      writeln!(f)?;
+    }
+    for change in &self.probability_changes {
+      match &change.bson_type {
+        Some(bson_type) => write!(f, "~ {} ({}):", change.path, bson_type)?,
+        None => write!(f, "~ {}:", change.path)?,
+      }
+      writeln!(
+        f,
+        " probability {:.2} -> {:.2}",
+        change.before, change.after
+      )?;
+    }
+    Ok(())
+  }
+}
+
+fn summarise(schema: &SchemaParser) -> BTreeMap<String, PathSummary> {
+  let mut paths: BTreeMap<String, PathSummary> = BTreeMap::new();
+  schema.walk_fields(&mut |field, parent_count| {
+    // the same path can show up both as a subdocument and as documents in an
+    // array, so merge them together.
+    let summary = paths.entry(field.path.to_string()).or_default();
+    summary.present += field.present_count();
+    summary.parent_count += parent_count;
+    for field_type in field.types.values() {
+      let count = field.type_count(field_type);
+      if count > 0 {
+        *summary
+          .types
+          .entry(field_type.bson_type.to_string())
+          .or_insert(0) += count;
+      }
+    }
+  });
+  paths
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use bson::doc;
+
+  fn schema(docs: Vec<bson::Document>) -> SchemaParser {
+    let mut schema_parser = SchemaParser::new();
+    for doc in docs {
+      schema_parser.write_bson(doc).unwrap();
+    }
+    schema_parser
+  }
+
+  #[test]
+  fn it_is_empty_for_same_schema() {
+    let before = schema(vec![doc! {"name": "Nori", "age": 4}]);
+    let after = schema(vec![doc! {"name": "Rey", "age": 2}]);
+    assert!(before.diff(&after).is_empty());
+  }
+
+  #[test]
+  fn it_finds_added_and_removed_paths() {
+    let before =
+      schema(vec![doc! {"name": "Nori", "owner": {"city": "Berlin"}}]);
+    let after = schema(vec![doc! {"name": "Rey", "owner": {"country": "DE"}}]);
+    let diff = before.diff(&after);
+    assert_eq!(diff.added, vec!["owner.country".to_string()]);
+    assert_eq!(diff.removed, vec!["owner.city".to_string()]);
+  }
+
+  #[test]
+  fn it_finds_type_changes() {
+    let before = schema(vec![doc! {"phone": 491234568789i64}]);
+    let after = schema(vec![doc! {"phone": "+491234568789"}]);
+    let diff = before.diff(&after);
+    assert_eq!(
+      diff.type_changes,
+      vec![TypeChange {
+        path: "phone".to_string(),
+        added: vec!["String".to_string()],
+        removed: vec!["Long".to_string()],
+      }]
+    );
+  }
+
+  #[test]
+  fn it_finds_probability_changes_above_threshold() {
+    let before = schema(vec![doc! {"name": "Nori"}, doc! {"name": "Rey"}]);
+    let after = schema(vec![doc! {"name": "Chashu"}, doc! {"age": 2}]);
+    let diff = before.diff(&after);
+    assert_eq!(
+      diff.probability_changes,
+      vec![ProbabilityChange {
+        path: "name".to_string(),
+        bson_type: None,
+        before: 1.0,
+        after: 0.5,
+      }]
+    );
+    assert!(before
+      .diff_with_threshold(&after, 0.6)
+      .probability_changes
+      .is_empty());
+  }
+
+  #[test]
+  fn it_renders_human_readable() {
+    let before = schema(vec![doc! {"name": "Nori"}]);
+    let after = schema(vec![doc! {"name": "Rey", "age": 2}]);
+    assert_eq!(before.diff(&after).to_string(), "+ age\n");
+  }
+}
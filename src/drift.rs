@@ -0,0 +1,469 @@
+use super::{field_type, ratio, FieldType, Result, SchemaParser, ValueType};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display};
+
+/// Thresholds used by `SchemaParser::drift_with_options` to decide which
+/// changes are worth reporting.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DriftOptions {
+  /// Smallest Kolmogorov-Smirnov statistic between the numeric values of a
+  /// field that is reported as a distribution shift.
+  pub distribution_threshold: f64,
+  /// Smallest change in the share of null or missing values.
+  pub null_rate_threshold: f32,
+  /// Smallest change in the ratio of distinct values to all values.
+  pub cardinality_threshold: f32,
+  /// How many of the most frequent strings are compared.
+  pub top_values: usize,
+}
+
+impl Default for DriftOptions {
+  fn default() -> Self {
+    DriftOptions {
+      distribution_threshold: 0.2,
+      null_rate_threshold: 0.05,
+      cardinality_threshold: 0.2,
+      top_values: 5,
+    }
+  }
+}
+
+/// Changes in the values of fields present in both schemas, as returned by
+/// `SchemaParser::drift`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct DriftReport {
+  pub drifts: Vec<Drift>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Drift {
+  /// Numeric values are distributed differently. `statistic` is the two
+  /// sample Kolmogorov-Smirnov statistic, between 0 and 1.
+  Distribution { path: String, statistic: f64 },
+  /// The most frequent strings changed.
+  TopValues {
+    path: String,
+    added: Vec<String>,
+    removed: Vec<String>,
+  },
+  /// The share of documents where the field is null or missing changed.
+  NullRate {
+    path: String,
+    before: f32,
+    after: f32,
+  },
+  /// The number of distinct values relative to all values changed.
+  Cardinality {
+    path: String,
+    before: usize,
+    after: usize,
+    before_ratio: f32,
+    after_ratio: f32,
+  },
+}
+
+impl Drift {
+  pub fn path(&self) -> &str {
+    match self {
+      Drift::Distribution { path, .. }
+      | Drift::TopValues { path, .. }
+      | Drift::NullRate { path, .. }
+      | Drift::Cardinality { path, .. } => path,
+    }
+  }
+}
+
+// Values seen for a single path in one schema.
+#[derive(Debug, Default)]
+struct PathValues {
+  parent_count: usize,
+  non_null: usize,
+  numbers: Vec<f64>,
+  strings: Vec<String>,
+  distinct: BTreeSet<String>,
+  total: usize,
+  // whether some type stopped keeping values at `max_values`, so the values
+  // are only the first few rather than a sample
+  capped: bool,
+}
+
+impl PathValues {
+  fn null_rate(&self) -> f32 {
+    1.0 - ratio(self.non_null, self.parent_count)
+  }
+
+  fn cardinality(&self) -> f32 {
+    ratio(self.distinct.len(), self.total)
+  }
+
+  fn top_values(&self, n: usize) -> Vec<String> {
+    let mut frequencies: BTreeMap<&str, usize> = BTreeMap::new();
+    for string in &self.strings {
+      *frequencies.entry(string).or_insert(0) += 1;
+    }
+    let mut frequencies: Vec<(&str, usize)> = frequencies.into_iter().collect();
+    // most frequent first, ties in alphabetical order
+    frequencies.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    frequencies
+      .into_iter()
+      .take(n)
+      .map(|(string, _)| string.to_string())
+      .collect()
+  }
+}
+
+impl DriftReport {
+  /// Returns `true` if no drift was found.
+  pub fn is_empty(&self) -> bool {
+    self.drifts.is_empty()
+  }
+
+  /// Returns the report as a serde_json string.
+  pub fn to_json(&self) -> Result<String> {
+    Ok(serde_json::to_string(self)?)
+  }
+
+  pub(crate) fn new(
+    before: &SchemaParser,
+    after: &SchemaParser,
+    options: &DriftOptions,
+  ) -> Self {
+    let before = collect(before);
+    let after = collect(after);
+    let mut drifts = Vec::new();
+
+    for (path, values) in &before {
+      let other = match after.get(path) {
+        Some(other) => other,
+        None => continue,
+      };
+
+      // distributions, top values and cardinality of capped values would
+      // only compare the first documents of either schema
+      let capped = values.capped || other.capped;
+
+      if !capped && !values.numbers.is_empty() && !other.numbers.is_empty() {
+        let statistic = ks_statistic(&values.numbers, &other.numbers);
+        if statistic > options.distribution_threshold {
+          drifts.push(Drift::Distribution {
+            path: path.to_string(),
+            statistic,
+          });
+        }
+      }
+
+      if !capped && !values.strings.is_empty() && !other.strings.is_empty() {
+        let top = values.top_values(options.top_values);
+        let other_top = other.top_values(options.top_values);
+        let added: Vec<String> = other_top
+          .iter()
+          .filter(|v| !top.contains(v))
+          .cloned()
+          .collect();
+        let removed: Vec<String> = top
+          .iter()
+          .filter(|v| !other_top.contains(v))
+          .cloned()
+          .collect();
+        if !added.is_empty() || !removed.is_empty() {
+          drifts.push(Drift::TopValues {
+            path: path.to_string(),
+            added,
+            removed,
+          });
+        }
+      }
+
+      let (from, to) = (values.null_rate(), other.null_rate());
+      if (to - from).abs() > options.null_rate_threshold {
+        drifts.push(Drift::NullRate {
+          path: path.to_string(),
+          before: from,
+          after: to,
+        });
+      }
+
+      let (from, to) = (values.cardinality(), other.cardinality());
+      if !capped
+        && values.total > 0
+        && other.total > 0
+        && (to - from).abs() > options.cardinality_threshold
+      {
+        drifts.push(Drift::Cardinality {
+          path: path.to_string(),
+          before: values.distinct.len(),
+          after: other.distinct.len(),
+          before_ratio: from,
+          after_ratio: to,
+        });
+      }
+    }
+
+    DriftReport { drifts }
+  }
+}
+
+impl Display for DriftReport {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.is_empty() {
+      return writeln!(f, "no drift");
+    }
+    for drift in &self.drifts {
+      match drift {
+        Drift::Distribution { path, statistic } => {
+          writeln!(f, "{}: distribution shift (KS {:.2})", path, statistic)?
+        }
+        Drift::TopValues {
+          path,
+          added,
+          removed,
+        } => writeln!(
+          f,
+          "{}: top values +[{}] -[{}]",
+          path,
+          added.join(", "),
+          removed.join(", ")
+        )?,
+        Drift::NullRate {
+          path,
+          before,
+          after,
+        } => writeln!(f, "{}: null rate {:.2} -> {:.2}", path, before, after)?,
+        Drift::Cardinality {
+          path,
+          before,
+          after,
+          ..
+        } => writeln!(f, "{}: distinct values {} -> {}", path, before, after)?,
+      }
+    }
+    Ok(())
+  }
+}
+
+fn collect(schema: &SchemaParser) -> BTreeMap<String, PathValues> {
+  let max_values = schema.options.max_values;
+  let mut paths: BTreeMap<String, PathValues> = BTreeMap::new();
+  schema.walk_fields(&mut |field, parent_count| {
+    let values = paths.entry(field.path.to_string()).or_default();
+    values.parent_count += parent_count;
+    values.non_null += field.present_count();
+    for field_type in field.types.values() {
+      if field_type.bson_type == field_type::NULL {
+        values.non_null -= field.type_count(field_type);
+      } else {
+        collect_type(field_type, max_values, values);
+      }
+    }
+  });
+  paths
+}
+
+fn collect_type(
+  field_type: &FieldType,
+  max_values: Option<usize>,
+  values: &mut PathValues,
+) {
+  if field_type.bson_type == field_type::ARRAY {
+    // element values are kept by the types inside the array
+    for child in field_type.types.values() {
+      collect_type(child, max_values, values);
+    }
+    return;
+  }
+  // types that never keep values, such as documents or dropped redacted
+  // values, stay below the cap however many times they were seen
+  if let Some(max) = max_values {
+    let kept = field_type.values.len();
+    if max > 0 && kept >= max && field_type.count > kept {
+      values.capped = true;
+    }
+  }
+  for value in &field_type.values {
+    match value {
+      ValueType::I32(num) => values.numbers.push(f64::from(*num)),
+      ValueType::I64(num) => values.numbers.push(*num as f64),
+      ValueType::FloatingPoint(num) if !num.is_nan() => {
+        values.numbers.push(*num)
+      }
+      ValueType::Str(string) if field_type.bson_type == field_type::STRING => {
+        values.strings.push(string.to_string())
+      }
+      _ => {}
+    }
+    values.distinct.insert(format!("{:?}", value));
+    values.total += 1;
+  }
+}
+
+// Largest distance between the empirical distribution functions of two
+// samples.
+fn ks_statistic(a: &[f64], b: &[f64]) -> f64 {
+  let mut a = a.to_vec();
+  let mut b = b.to_vec();
+  a.sort_by(|x, y| x.total_cmp(y));
+  b.sort_by(|x, y| x.total_cmp(y));
+
+  let (mut i, mut j) = (0, 0);
+  let mut statistic: f64 = 0.0;
+  while i < a.len() && j < b.len() {
+    let x = a[i].min(b[j]);
+    while i < a.len() && a[i] <= x {
+      i += 1;
+    }
+    while j < b.len() && b[j] <= x {
+      j += 1;
+    }
+    let distance =
+      (i as f64 / a.len() as f64 - j as f64 / b.len() as f64).abs();
+    statistic = statistic.max(distance);
+  }
+  statistic
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::SchemaParserOptions;
+  use bson::{doc, Bson};
+
+  fn schema(docs: Vec<bson::Document>) -> SchemaParser {
+    let mut schema_parser = SchemaParser::new();
+    for doc in docs {
+      schema_parser.write_bson(doc).unwrap();
+    }
+    schema_parser
+  }
+
+  #[allow(clippy::float_cmp)]
+  #[test]
+  fn it_computes_ks_statistic() {
+    assert_eq!(ks_statistic(&[1.0, 2.0, 3.0], &[1.0, 2.0, 3.0]), 0.0);
+    assert_eq!(ks_statistic(&[1.0, 2.0], &[3.0, 4.0]), 1.0);
+    assert_eq!(ks_statistic(&[1.0, 2.0, 3.0, 4.0], &[3.0, 4.0]), 0.5);
+  }
+
+  #[test]
+  fn it_finds_distribution_shift() {
+    let before = schema((0..20).map(|i| doc! {"age": i}).collect());
+    let after = schema((10..30).map(|i| doc! {"age": i}).collect());
+    let report = before.drift(&after);
+    assert_eq!(
+      report.drifts,
+      vec![Drift::Distribution {
+        path: "age".to_string(),
+        statistic: 0.5,
+      }]
+    );
+  }
+
+  #[test]
+  fn it_finds_top_value_changes() {
+    let before = schema(vec![
+      doc! {"type": "Cat"},
+      doc! {"type": "Cat"},
+      doc! {"type": "Dog"},
+    ]);
+    let after = schema(vec![
+      doc! {"type": "Cat"},
+      doc! {"type": "Cat"},
+      doc! {"type": "Bird"},
+    ]);
+    let options = DriftOptions {
+      top_values: 2,
+      ..DriftOptions::default()
+    };
+    let report = before.drift_with_options(&after, &options);
+    assert_eq!(
+      report.drifts,
+      vec![Drift::TopValues {
+        path: "type".to_string(),
+        added: vec!["Bird".to_string()],
+        removed: vec!["Dog".to_string()],
+      }]
+    );
+  }
+
+  #[test]
+  fn it_finds_null_rate_changes() {
+    let before = schema(vec![doc! {"name": "Nori"}, doc! {"name": "Rey"}]);
+    let after = schema(vec![doc! {"name": "Nori"}, doc! {"name": Bson::Null}]);
+    let report = before.drift(&after);
+    assert!(report.drifts.contains(&Drift::NullRate {
+      path: "name".to_string(),
+      before: 0.0,
+      after: 0.5,
+    }));
+  }
+
+  #[test]
+  fn it_finds_cardinality_jumps() {
+    let before = schema((0..10).map(|_| doc! {"status": 1}).collect());
+    let after = schema((0..10).map(|i| doc! {"status": i}).collect());
+    let report = before.drift(&after);
+    assert!(report.drifts.iter().any(|drift| match drift {
+      Drift::Cardinality { before, after, .. } => *before == 1 && *after == 10,
+      _ => false,
+    }));
+  }
+
+  #[test]
+  fn it_skips_capped_values() {
+    let options = SchemaParserOptions {
+      max_values: Some(10),
+      ..SchemaParserOptions::default()
+    };
+    let schema = |docs: Vec<bson::Document>| {
+      let mut schema_parser =
+        SchemaParser::with_options(options.clone()).unwrap();
+      for doc in docs {
+        schema_parser.write_bson(doc).unwrap();
+      }
+      schema_parser
+    };
+    // the first ten ages are the same, the rest are not
+    let ages = |offset| {
+      (0..20)
+        .map(|i| doc! {"age": if i < 10 { i } else { i + offset }})
+        .collect()
+    };
+    let before = schema(ages(0));
+    let after = schema(ages(100));
+    assert!(before.drift(&after).is_empty());
+
+    let mut after = schema(ages(100));
+    for _ in 0..5 {
+      after.write_bson(doc! {"age": Bson::Null}).unwrap();
+    }
+    let report = before.drift(&after);
+    assert_eq!(report.drifts.len(), 1);
+    assert_eq!(report.drifts[0].path(), "age");
+    assert!(matches!(report.drifts[0], Drift::NullRate { .. }));
+  }
+
+  #[test]
+  fn it_compares_values_next_to_types_without_values() {
+    let options = SchemaParserOptions {
+      max_values: Some(100),
+      ..SchemaParserOptions::default()
+    };
+    let schema = |status: &str| {
+      let mut schema_parser =
+        SchemaParser::with_options(options.clone()).unwrap();
+      for _ in 0..10 {
+        schema_parser.write_bson(doc! {"status": status}).unwrap();
+      }
+      // documents keep no values, which must not read as capped values
+      schema_parser
+        .write_bson(doc! {"status": {"code": 1}})
+        .unwrap();
+      schema_parser
+    };
+    let report = schema("active").drift(&schema("deleted"));
+    assert!(report
+      .drifts
+      .iter()
+      .any(|drift| matches!(drift, Drift::TopValues { .. })));
+  }
+}
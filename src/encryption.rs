@@ -0,0 +1,474 @@
+use super::field_type::DOCUMENT;
+use super::{
+  doc, ratio, Bson, Document, Field, FieldType, PiiKind, Result, SchemaParser,
+};
+use std::fmt::{self, Display};
+
+/// Fields whose kept values are at least this share distinct are suggested
+/// for deterministic encryption by `SchemaParser::suggest_encryption`, as
+/// they look like identifiers queried by equality.
+pub static DEFAULT_CARDINALITY_THRESHOLD: f32 = 0.9;
+
+// `$jsonSchema` types that deterministic encryption can be queried by
+// equality on.
+static EQUALITY_TYPES: &[&str] = &[
+  "string",
+  "int",
+  "long",
+  "objectId",
+  "date",
+  "binData",
+  "timestamp",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EncryptionAlgorithm {
+  /// Equal values encrypt to equal ciphertext, so the field can be queried
+  /// by equality, but how often values repeat shows.
+  Deterministic,
+  /// Values encrypt to different ciphertext every time, so the field can't
+  /// be queried.
+  Random,
+}
+
+impl EncryptionAlgorithm {
+  /// Name of the algorithm in a client-side field level encryption
+  /// `schemaMap`.
+  pub fn name(&self) -> &'static str {
+    match self {
+      EncryptionAlgorithm::Deterministic => {
+        "AEAD_AES_256_CBC_HMAC_SHA_512-Deterministic"
+      }
+      EncryptionAlgorithm::Random => "AEAD_AES_256_CBC_HMAC_SHA_512-Random",
+    }
+  }
+}
+
+/// Fields to encrypt, and fields that are encrypted already, as returned by
+/// `SchemaParser::suggest_encryption`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct EncryptionReport {
+  pub suggestions: Vec<EncryptionSuggestion>,
+  pub encrypted: Vec<EncryptedField>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EncryptionSuggestion {
+  pub path: String,
+  /// `$jsonSchema` names of the types of this field, i.e. `string`.
+  pub bson_types: Vec<String>,
+  pub algorithm: EncryptionAlgorithm,
+  /// Share of the kept values of this field that are distinct, or `None` if
+  /// no values were kept, i.e. under `Redaction::Drop`. Hashed values of
+  /// `Redaction::Hash` count like the values themselves. With no cardinality
+  /// to go by, the algorithm is `Random`, even if the field is queried.
+  pub cardinality: Option<f32>,
+  /// Kinds of personal data found in this field or in fields nested in it.
+  /// Empty if only the name of a field suggests personal data.
+  pub kinds: Vec<PiiKind>,
+}
+
+/// A field with values that are encrypted already, as Binary subtype 6.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EncryptedField {
+  pub path: String,
+  pub count: usize,
+}
+
+impl EncryptionReport {
+  /// Returns `true` if there is nothing to encrypt and nothing encrypted.
+  pub fn is_empty(&self) -> bool {
+    self.suggestions.is_empty() && self.encrypted.is_empty()
+  }
+
+  /// Returns the report as a serde_json string.
+  pub fn to_json(&self) -> Result<String> {
+    Ok(serde_json::to_string(self)?)
+  }
+
+  /// The suggestions as the `$jsonSchema` of a client-side field level
+  /// encryption `schemaMap`, to be keyed with the namespace of the
+  /// collection. It still needs a `keyId` in `encryptMetadata`.
+  pub fn schema_map(&self) -> Document {
+    let mut schema = doc! {"bsonType": "object"};
+    for suggestion in &self.suggestions {
+      let bson_type = match suggestion.bson_types.as_slice() {
+        [bson_type] => Bson::from(bson_type),
+        bson_types => Bson::from(bson_types.to_vec()),
+      };
+      let encrypt = doc! {
+        "encrypt": {
+          "bsonType": bson_type,
+          "algorithm": suggestion.algorithm.name(),
+        },
+      };
+      insert_property(&mut schema, &suggestion.path, encrypt);
+    }
+    schema
+  }
+
+  /// The suggestions as the `encryptedFields` of a Queryable Encryption
+  /// collection, where deterministic ones are queryable by equality.
+  /// `keyId`s are null, for `createEncryptedCollection` to create them.
+  /// Fields of more than one type are left out, as encrypted fields have a
+  /// single one.
+  pub fn encrypted_fields(&self) -> Document {
+    let fields: Vec<Bson> = self
+      .suggestions
+      .iter()
+      .filter_map(|suggestion| {
+        let bson_type = match suggestion.bson_types.as_slice() {
+          [bson_type] => bson_type,
+          _ => return None,
+        };
+        let mut field = doc! {
+          "path": &suggestion.path,
+          "bsonType": bson_type,
+          "keyId": Bson::Null,
+        };
+        if suggestion.algorithm == EncryptionAlgorithm::Deterministic {
+          field.insert("queries", doc! {"queryType": "equality"});
+        }
+        Some(Bson::Document(field))
+      })
+      .collect();
+    doc! {"fields": fields}
+  }
+
+  pub(crate) fn new(schema: &SchemaParser, threshold: f32) -> Self {
+    let mut suggestions: Vec<EncryptionSuggestion> = Vec::new();
+    // ancestors sort before the fields nested in them
+    for found in schema.pii().fields {
+      let field = match encryptable(schema, &found.path) {
+        Some(field) => field,
+        None => continue,
+      };
+      let kinds = found.kinds.iter().map(|found| found.kind);
+      // fields nested in one that is encrypted already are encrypted with it
+      let parent = suggestions
+        .iter_mut()
+        .find(|suggestion| covers(&suggestion.path, &field.path));
+      if let Some(parent) = parent {
+        for kind in kinds {
+          if !parent.kinds.contains(&kind) {
+            parent.kinds.push(kind);
+          }
+        }
+        continue;
+      }
+      if let Some(mut suggestion) = suggest(field, threshold) {
+        suggestion.kinds = kinds.collect();
+        suggestions.push(suggestion);
+      }
+    }
+
+    let mut encrypted = Vec::new();
+    schema.walk_fields(&mut |field, _| {
+      let count: usize = field.types.values().map(count_encrypted).sum();
+      if count > 0 {
+        encrypted.push(EncryptedField {
+          path: field.path.to_string(),
+          count,
+        });
+      }
+    });
+    encrypted.sort_by(|a, b| a.path.cmp(&b.path));
+    EncryptionReport {
+      suggestions,
+      encrypted,
+    }
+  }
+}
+
+// Returns the field at `path`, or the array of documents it is nested in,
+// as fields inside arrays can only be encrypted with the whole array.
+fn encryptable<'a>(schema: &'a SchemaParser, path: &str) -> Option<&'a Field> {
+  let mut schema = schema;
+  let mut names = path.split('.').peekable();
+  while let Some(name) = names.next() {
+    let field = schema.fields.get(name)?;
+    if names.peek().is_none() {
+      return Some(field);
+    }
+    let nested = field.types.get(DOCUMENT).and_then(|t| t.schema.as_ref());
+    match nested {
+      Some(nested) => schema = nested,
+      None => return Some(field),
+    }
+  }
+  None
+}
+
+// `Option::is_some_and` needs Rust 1.70
+#[allow(clippy::unnecessary_map_or)]
+fn covers(parent: &str, path: &str) -> bool {
+  path
+    .strip_prefix(parent)
+    .map_or(false, |rest| rest.is_empty() || rest.starts_with('.'))
+}
+
+fn suggest(field: &Field, threshold: f32) -> Option<EncryptionSuggestion> {
+  let mut field_types: Vec<&FieldType> = field.types.values().collect();
+  field_types.sort_by(|a, b| a.bson_type.cmp(&b.bson_type));
+  let mut bson_types = Vec::new();
+  let mut distinct = 0;
+  let mut values = 0;
+  for field_type in field_types {
+    let bson_type = match json_schema_type(&field_type.bson_type) {
+      Some(bson_type) => bson_type,
+      None => continue,
+    };
+    // ciphertext doesn't need encrypting again
+    if field_type.encrypted == Some(field_type.count) {
+      continue;
+    }
+    bson_types.push(bson_type.to_string());
+    distinct += field_type.get_unique();
+    values += field_type.values.len();
+  }
+  if bson_types.is_empty() {
+    return None;
+  }
+  let cardinality = if values > 0 {
+    Some(ratio(distinct, values))
+  } else {
+    None
+  };
+  let algorithm = match (bson_types.as_slice(), cardinality) {
+    ([bson_type], Some(cardinality))
+      if EQUALITY_TYPES.contains(&bson_type.as_str())
+        && cardinality >= threshold =>
+    {
+      EncryptionAlgorithm::Deterministic
+    }
+    _ => EncryptionAlgorithm::Random,
+  };
+  Some(EncryptionSuggestion {
+    path: field.path.to_string(),
+    bson_types,
+    algorithm,
+    cardinality,
+    kinds: Vec::new(),
+  })
+}
+
+// Encrypted values of `field_type`, and of the types of array elements in
+// it.
+fn count_encrypted(field_type: &FieldType) -> usize {
+  field_type.encrypted.unwrap_or(0)
+    + field_type
+      .types
+      .values()
+      .map(count_encrypted)
+      .sum::<usize>()
+}
+
+// Maps this crate's BSON type names to `$jsonSchema` ones. Null, undefined,
+// MinKey and MaxKey can't be encrypted.
+fn json_schema_type(bson_type: &str) -> Option<&'static str> {
+  let name = match bson_type {
+    "String" => "string",
+    "Int32" => "int",
+    "Long" => "long",
+    "Double" => "double",
+    "Decimal128" => "decimal",
+    "Boolean" => "bool",
+    "UtcDatetime" => "date",
+    "ObjectId" => "objectId",
+    "BinData" => "binData",
+    "Timestamp" => "timestamp",
+    "Regex" => "regex",
+    "JavaScriptCode" => "javascript",
+    "JavaScriptCodeWithScope" => "javascriptWithScope",
+    "Symbol" => "symbol",
+    "DbPointer" => "dbPointer",
+    "Document" => "object",
+    "Array" => "array",
+    _ => return None,
+  };
+  Some(name)
+}
+
+// Adds `property` at the dotted `path` of an object `$jsonSchema`, along
+// with the objects leading to it.
+fn insert_property(schema: &mut Document, path: &str, property: Document) {
+  let properties = schema
+    .entry("properties".to_string())
+    .or_insert_with(|| Bson::Document(Document::new()));
+  let properties = match properties {
+    Bson::Document(properties) => properties,
+    _ => return,
+  };
+  match path.split_once('.') {
+    Some((name, rest)) => {
+      let parent = properties
+        .entry(name.to_string())
+        .or_insert_with(|| Bson::Document(doc! {"bsonType": "object"}));
+      if let Bson::Document(parent) = parent {
+        insert_property(parent, rest, property);
+      }
+    }
+    None => {
+      properties.insert(path, property);
+    }
+  }
+}
+
+impl Display for EncryptionReport {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.is_empty() {
+      return writeln!(f, "nothing to encrypt");
+    }
+    for suggestion in &self.suggestions {
+      let cardinality = match suggestion.cardinality {
+        Some(cardinality) => format!("{:.2}% distinct", cardinality * 100.0),
+        None => "no values kept to count distinct ones".to_string(),
+      };
+      writeln!(
+        f,
+        "{}: encrypt {} with {} ({})",
+        suggestion.path,
+        suggestion.bson_types.join("/"),
+        suggestion.algorithm.name(),
+        cardinality
+      )?;
+    }
+    for field in &self.encrypted {
+      writeln!(
+        f,
+        "{}: {} value(s) encrypted already",
+        field.path, field.count
+      )?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{Redaction, SchemaParserOptions};
+  use bson::spec::BinarySubtype;
+  use bson::Binary;
+
+  fn schema() -> SchemaParser {
+    let mut schema_parser = SchemaParser::new();
+    for i in 0..10 {
+      let ciphertext = Binary {
+        subtype: BinarySubtype::Encrypted,
+        bytes: vec![2, i],
+      };
+      schema_parser
+        .write_bson(doc! {
+          "name": "Nori",
+          "owner": {"email": format!("owner{}@example.com", i)},
+          "phone": if i % 2 == 0 { "+49 30 1234567" } else { "+49 30 7654321" },
+          "creditCard": {"number": "4111 1111 1111 1111", "expiry": "12/30"},
+          "visits": [{"ip": "192.168.0.1"}],
+          "ssn": ciphertext,
+        })
+        .unwrap();
+    }
+    schema_parser.flush();
+    schema_parser
+  }
+
+  #[test]
+  fn it_suggests_algorithms() {
+    let report = schema().suggest_encryption();
+    let paths: Vec<&str> = report
+      .suggestions
+      .iter()
+      .map(|suggestion| suggestion.path.as_str())
+      .collect();
+    assert_eq!(paths, vec!["creditCard", "owner.email", "phone", "visits"]);
+
+    let credit_card = &report.suggestions[0];
+    assert_eq!(credit_card.bson_types, vec!["object"]);
+    assert_eq!(credit_card.algorithm, EncryptionAlgorithm::Random);
+    assert_eq!(credit_card.kinds, vec![PiiKind::CreditCard]);
+
+    let email = &report.suggestions[1];
+    assert_eq!(email.algorithm, EncryptionAlgorithm::Deterministic);
+    assert_eq!(email.cardinality, Some(1.0));
+    // two phone numbers repeat too often to encrypt them deterministically
+    assert_eq!(report.suggestions[2].algorithm, EncryptionAlgorithm::Random);
+    // fields in arrays are encrypted with the array
+    assert_eq!(report.suggestions[3].kinds, vec![PiiKind::IpAddress]);
+
+    assert_eq!(
+      report.encrypted,
+      vec![EncryptedField {
+        path: "ssn".to_string(),
+        count: 10
+      }]
+    );
+  }
+
+  #[test]
+  fn it_counts_distinct_redacted_values() {
+    let suggestion = |redaction| {
+      let options = SchemaParserOptions {
+        redact: vec!["owner.email".to_string()],
+        redaction,
+        salt: "pepper".to_string(),
+        ..SchemaParserOptions::default()
+      };
+      let mut schema_parser = SchemaParser::with_options(options).unwrap();
+      for i in 0..10 {
+        let email = format!("owner{}@example.com", i);
+        schema_parser
+          .write_bson(doc! {"owner": {"email": email}})
+          .unwrap();
+      }
+      schema_parser.flush();
+      let report = schema_parser.suggest_encryption();
+      report.suggestions[0].clone()
+    };
+
+    let hashed = suggestion(Redaction::Hash);
+    assert_eq!(hashed.path, "owner.email");
+    assert_eq!(hashed.cardinality, Some(1.0));
+    assert_eq!(hashed.algorithm, EncryptionAlgorithm::Deterministic);
+
+    let dropped = suggestion(Redaction::Drop);
+    assert_eq!(dropped.kinds, vec![PiiKind::Email]);
+    assert_eq!(dropped.cardinality, None);
+    assert_eq!(dropped.algorithm, EncryptionAlgorithm::Random);
+  }
+
+  #[test]
+  fn it_builds_schema_maps() {
+    let report = schema().suggest_encryption();
+    let schema_map = report.schema_map();
+    let email = schema_map
+      .get_document("properties")
+      .and_then(|properties| properties.get_document("owner"))
+      .and_then(|owner| owner.get_document("properties"))
+      .and_then(|properties| properties.get_document("email"))
+      .unwrap();
+    assert_eq!(
+      email,
+      &doc! {
+        "encrypt": {
+          "bsonType": "string",
+          "algorithm": "AEAD_AES_256_CBC_HMAC_SHA_512-Deterministic",
+        },
+      }
+    );
+
+    let encrypted_fields = report.encrypted_fields();
+    let fields = encrypted_fields.get_array("fields").unwrap();
+    assert_eq!(fields.len(), 4);
+    assert_eq!(
+      fields[1],
+      Bson::Document(doc! {
+        "path": "owner.email",
+        "bsonType": "string",
+        "keyId": Bson::Null,
+        "queries": {"queryType": "equality"},
+      })
+    );
+  }
+}
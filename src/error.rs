@@ -1,4 +1,6 @@
-use failure::{Backtrace, Context, Fail};
+use crate::ingest::Location;
+use bson::spec::ElementType;
+use std::error;
 use std::fmt::{self, Display};
 use std::io;
 use std::result;
@@ -6,11 +8,12 @@ use std::result;
 /// A specialized [`Result`] type for this crate's operations.
 ///
 /// This is generally used to avoid writing out [Error] directly and
-/// is otherwise a direct mapping to [`Result`].
+/// is otherwise a direct mapping to [`Result`]. Other error types can still be
+/// named, i.e. `Result<(), JsValue>`.
 ///
 /// [`Result`]: https://doc.rust-lang.org/nightly/std/result/enum.Result.html
 /// [`Error`]: std.struct.Error.html
-pub type Result<T> = result::Result<T, Error>;
+pub type Result<T, E = Error> = result::Result<T, E>;
 
 /// A list enumerating the categories of errors in this crate.
 ///
@@ -20,30 +23,56 @@ pub type Result<T> = result::Result<T, Error>;
 /// It is used with the [`Error`] struct.
 ///
 /// [`Error`]: std.struct.Error.html
-#[derive(Debug, Fail)]
+#[derive(Debug)]
+#[non_exhaustive]
 pub enum ErrorKind {
-  /// Any error not part of this list.
-  #[fail(display = "Generic error.")]
-  Other,
   /// An error caused by an IO failure.
-  #[fail(display = "{}", _0)]
-  Io(#[cause] io::Error),
-  /// An error caused by an unknown input type.
-  #[fail(
-    display = "Unknown Input Type for 'docs'. Must be an array or a MongoDB Cursor"
-  )]
-  UnknownInputType,
-  /// An parse error
-  #[fail(display = "Parse Error in input document")]
-  ParseError,
+  Io(io::Error),
+  /// Malformed json, or a schema that can't be serialized to json.
+  Json(serde_json::Error),
+  /// A json value that isn't valid Extended JSON, i.e. `{"$oid": 1}`.
+  ExtendedJson(bson::extjson::de::Error),
+  /// A BSON document with a valid length but malformed contents.
+  Bson(bson::de::Error),
+  /// A top level value that is not a document, i.e. a json array.
+  NotADocument(ElementType),
+  /// A value that doesn't fit its CSV column type, i.e. `two` in an
+  /// `int32()` column.
+  InvalidValue(String),
+  /// Malformed CSV, or an IO failure while reading it.
+  #[cfg(feature = "csv")]
+  Csv(csv::Error),
+  /// Input that can't be read any further: a truncated BSON document, a
+  /// broken `mongodump --archive` file, an invalid CSV header or compressed
+  /// input without the feature to decompress it.
+  InvalidInput(String),
+  /// `SchemaParserOptions` that can't be used, i.e. `Redaction::Hash`
+  /// without a salt.
+  InvalidOptions(String),
+  /// A document that goes past one of the limits of
+  /// `SchemaParserOptions`, with `OnLimit::Error`.
+  LimitExceeded {
+    /// Name of the limit.
+    limit: &'static str,
+    /// The configured maximum.
+    max: usize,
+  },
+  /// Any error not part of this list.
+  Other(String),
 }
 
 /// A specialized [`Error`] type for this crate's operations.
 ///
+/// Besides its [`ErrorKind`], an error knows where in the input it was found
+/// and in which field, if that applies.
+///
 /// [`Error`]: https://doc.rust-lang.org/nightly/std/error/trait.Error.html
+/// [`ErrorKind`]: enum.ErrorKind.html
 #[derive(Debug)]
 pub struct Error {
-  inner: Context<ErrorKind>,
+  kind: ErrorKind,
+  location: Option<Location>,
+  path: Option<String>,
 }
 
 impl Error {
@@ -51,35 +80,152 @@ impl Error {
   ///
   /// [`ErrorKind`]: enum.ErrorKind.html
   pub fn kind(&self) -> &ErrorKind {
-    &*self.inner.get_context()
+    &self.kind
+  }
+
+  /// Where in the input the error was found: a line, a byte offset, an
+  /// element of a json array, or the index of a document passed to
+  /// `write_json`.
+  pub fn location(&self) -> Option<Location> {
+    self.location
+  }
+
+  /// Dotted path of the field the error was found in.
+  pub fn path(&self) -> Option<&str> {
+    self.path.as_deref()
   }
-}
 
-impl Fail for Error {
-  fn cause(&self) -> Option<&Fail> {
-    self.inner.cause()
+  // Sets the location, unless a more precise one is already known.
+  pub(crate) fn at(mut self, location: Location) -> Self {
+    self.location.get_or_insert(location);
+    self
   }
 
-  fn backtrace(&self) -> Option<&Backtrace> {
-    self.inner.backtrace()
+  pub(crate) fn in_field(mut self, path: String) -> Self {
+    self.path = Some(path);
+    self
+  }
+}
+
+impl Display for ErrorKind {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ErrorKind::Io(e) => Display::fmt(e, f),
+      ErrorKind::Json(e) => Display::fmt(e, f),
+      ErrorKind::ExtendedJson(e) => Display::fmt(e, f),
+      ErrorKind::Bson(e) => write!(f, "malformed BSON document: {}", e),
+      ErrorKind::NotADocument(element_type) => {
+        write!(f, "expected a document, found {:?}", element_type)
+      }
+      ErrorKind::InvalidValue(message) => f.write_str(message),
+      #[cfg(feature = "csv")]
+      ErrorKind::Csv(e) => Display::fmt(e, f),
+      ErrorKind::InvalidInput(message) => f.write_str(message),
+      ErrorKind::InvalidOptions(message) => f.write_str(message),
+      ErrorKind::LimitExceeded { limit, max } => {
+        write!(f, "exceeds the {} limit of {}", limit, max)
+      }
+      ErrorKind::Other(message) => f.write_str(message),
+    }
   }
 }
 
 impl Display for Error {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    Display::fmt(&self.inner, f)
+    match (&self.location, &self.path) {
+      (Some(location), Some(path)) => {
+        write!(f, "{}, field '{}': ", location, path)?
+      }
+      (Some(location), None) => write!(f, "{}: ", location)?,
+      (None, Some(path)) => write!(f, "field '{}': ", path)?,
+      (None, None) => {}
+    }
+    Display::fmt(&self.kind, f)
+  }
+}
+
+impl error::Error for Error {
+  fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+    match &self.kind {
+      ErrorKind::Io(e) => Some(e),
+      ErrorKind::Json(e) => Some(e),
+      ErrorKind::ExtendedJson(e) => Some(e),
+      ErrorKind::Bson(e) => Some(e),
+      #[cfg(feature = "csv")]
+      ErrorKind::Csv(e) => Some(e),
+      _ => None,
+    }
   }
 }
 
 impl From<ErrorKind> for Error {
   fn from(kind: ErrorKind) -> Error {
-    let inner = Context::new(kind);
-    Error { inner }
+    Error {
+      kind,
+      location: None,
+      path: None,
+    }
+  }
+}
+
+impl From<io::Error> for Error {
+  fn from(e: io::Error) -> Error {
+    ErrorKind::Io(e).into()
+  }
+}
+
+impl From<serde_json::Error> for Error {
+  fn from(e: serde_json::Error) -> Error {
+    ErrorKind::Json(e).into()
   }
 }
 
-impl From<Context<ErrorKind>> for Error {
-  fn from(inner: Context<ErrorKind>) -> Error {
-    Error { inner }
+impl From<bson::extjson::de::Error> for Error {
+  fn from(e: bson::extjson::de::Error) -> Error {
+    ErrorKind::ExtendedJson(e).into()
+  }
+}
+
+impl From<bson::de::Error> for Error {
+  fn from(e: bson::de::Error) -> Error {
+    ErrorKind::Bson(e).into()
+  }
+}
+
+#[cfg(feature = "csv")]
+impl From<csv::Error> for Error {
+  fn from(e: csv::Error) -> Error {
+    ErrorKind::Csv(e).into()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_shows_location_and_path() {
+    let error = Error::from(ErrorKind::InvalidValue(
+      "'two' is not a valid int32".to_string(),
+    ))
+    .at(Location::Line(3))
+    .in_field("owner.age".to_string());
+    assert_eq!(
+      error.to_string(),
+      "line 3, field 'owner.age': 'two' is not a valid int32"
+    );
+    // the first location is kept
+    let error = error.at(Location::Document(0));
+    assert_eq!(error.location(), Some(Location::Line(3)));
+    assert_eq!(error.path(), Some("owner.age"));
+  }
+
+  #[test]
+  fn it_keeps_the_source() {
+    let json = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+    let error = Error::from(json).at(Location::Document(2));
+    assert!(matches!(error.kind(), ErrorKind::Json(_)));
+    assert!(error::Error::source(&error).is_some());
+    assert!(error.to_string().starts_with("document 2: EOF"));
   }
 }
@@ -1,4 +1,5 @@
-use super::{Bson, FieldType};
+use super::geo::{self, Geospatial};
+use super::{Bson, FieldType, SchemaParserOptions, WriteContext};
 use std::collections::HashMap;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -9,6 +10,13 @@ pub struct Field {
   pub bson_types: Vec<String>,
   pub probability: f32,
   pub types: HashMap<String, FieldType>,
+  // set for fields holding GeoJSON or legacy coordinate pairs
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub geo: Option<Geospatial>,
+  // number of documents this field was missing from; these are counted as
+  // Null in `types`, but the Compass output reports them separately.
+  #[serde(skip)]
+  pub missing: usize,
 }
 
 impl Field {
@@ -24,55 +32,91 @@ impl Field {
       bson_types: Vec::new(),
       probability: 0.0,
       types: HashMap::new(),
+      geo: None,
+      missing: 0,
     }
   }
 
-  pub fn create_type(&mut self, value: &Bson) {
-    let mut field_type = FieldType::new(&self.path, &FieldType::get_type(&value));
-    field_type.add_to_type(&value, self.count);
+  pub fn create_type(&mut self, value: &Bson, context: &WriteContext) {
+    let mut field_type =
+      FieldType::new(&self.path, &FieldType::get_type(value));
+    field_type.add_to_type(value, self.count, context);
     self.bson_types.push(field_type.bson_type.to_string());
     self
       .types
-      .insert(FieldType::get_type(&value), field_type.to_owned());
+      .insert(FieldType::get_type(value), field_type.to_owned());
   }
 
   pub fn does_field_type_exist(&mut self, value: &Bson) -> bool {
-    self.bson_types.contains(&FieldType::get_type(&value))
+    self.bson_types.contains(&FieldType::get_type(value))
   }
 
   pub fn get_path(name: String, path: Option<String>) -> String {
     match path {
       None => name,
       Some(mut path) => {
-        path.push_str(".");
+        path.push('.');
         path.push_str(&name);
         path
       }
     }
   }
 
-  pub fn finalise_field(&mut self, parent_count: usize) {
+  pub fn finalise_field(
+    &mut self,
+    parent_count: usize,
+    options: &SchemaParserOptions,
+  ) {
     self.set_probability(parent_count);
     for field_type in self.types.values_mut() {
-      field_type.finalise_type(self.count);
+      field_type.finalise_type(self.count, options);
     }
+    self.geo = geo::geospatial(self);
   }
 
   pub fn update_for_missing(&mut self, missing: usize) {
-    // create new field_types of "Null" for missing fields.
-    let mut null_field_type = FieldType::new(&self.path, &FieldType::get_type(&Bson::Null));
-    null_field_type.add_to_type(&Bson::Null, self.count);
-    null_field_type.count = missing;
-    self.types.insert(
-      crate::field_type::NULL.to_string(),
-      null_field_type.to_owned(),
-    );
-    self.bson_types.push(null_field_type.bson_type);
+    // create new field_types of "Null" for missing fields, or add to the
+    // existing one if this field already had explicit nulls.
+    match self.types.get_mut(crate::field_type::NULL) {
+      Some(null_field_type) => null_field_type.count += missing,
+      None => {
+        let mut null_field_type =
+          FieldType::new(&self.path, &FieldType::get_type(&Bson::Null));
+        null_field_type.add_to_type(
+          &Bson::Null,
+          self.count,
+          &WriteContext::default(),
+        );
+        null_field_type.count = missing;
+        self.types.insert(
+          crate::field_type::NULL.to_string(),
+          null_field_type.to_owned(),
+        );
+        self.bson_types.push(null_field_type.bson_type);
+      }
+    }
+    self.missing += missing;
     // need to update internal field count, since otherwise on the next
     // iteration we will get integer overflow
     self.update_count_by(missing);
   }
 
+  /// Number of documents this field was present in; after finalising,
+  /// `count` also includes the documents it was missing from.
+  pub fn present_count(&self) -> usize {
+    self.count.saturating_sub(self.missing)
+  }
+
+  /// Number of times `field_type` was seen, leaving out the Null values that
+  /// stand in for documents this field was missing from.
+  pub fn type_count(&self, field_type: &FieldType) -> usize {
+    if field_type.bson_type == crate::field_type::NULL {
+      field_type.count.saturating_sub(self.missing)
+    } else {
+      field_type.count
+    }
+  }
+
   pub fn update_count(&mut self) {
     self.count += 1
   }
@@ -1,5 +1,12 @@
 #![allow(clippy::option_map_unit_fn)]
-use super::{Bson, SchemaParser, ValueType, HashMap, console};
+use super::geo::GeoCounts;
+use super::redact::{is_redacted, redact};
+use super::{
+  console, pii, ratio, Bson, Document, HashMap, PiiKind, Redaction,
+  SchemaParser, SchemaParserOptions, ValueType, WriteContext,
+};
+use bson::spec::BinarySubtype;
+use std::cmp::Ordering;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct FieldType {
@@ -12,6 +19,12 @@ pub struct FieldType {
   pub values: Vec<ValueType>,
   #[serde(skip_serializing_if = "Vec::is_empty")]
   pub lengths: Vec<usize>,
+  // summary of `lengths`, for arrays
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub length_stats: Option<LengthStats>,
+  // types of every position, for arrays that look like tuples
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub tuple: Option<Vec<TuplePosition>>,
   pub has_duplicates: bool,
   #[serde(skip_serializing_if = "Option::is_none")]
   #[serde(flatten)]
@@ -20,8 +33,144 @@ pub struct FieldType {
   pub types: HashMap<String, FieldType>,
   #[serde(skip_serializing_if = "Option::is_none")]
   pub unique: Option<usize>,
+  // values of this type that were nested too deep, or had too many
+  // elements, to be analysed in full
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub truncated: Option<usize>,
+  // values of this type that are encrypted already, as Binary subtype 6
+  // holding client-side field level or Queryable Encryption ciphertext
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub encrypted: Option<usize>,
+  // `_id`s of the first few documents this type was seen in.
+  #[serde(skip)]
+  pub document_ids: Vec<ValueType>,
+  // types found at every position of the arrays of this type so far
+  #[serde(skip)]
+  positions: Positions,
+  // GeoJSON geometries and legacy coordinate pairs among the values
+  #[serde(skip)]
+  pub(crate) geo: GeoCounts,
+  // how many values of this type looked like every kind of personal data,
+  // counted before they are redacted
+  #[serde(skip)]
+  pub pii: HashMap<PiiKind, usize>,
 }
 
+/// Sizes of the arrays of a field, counting every array once.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LengthStats {
+  pub min: usize,
+  pub max: usize,
+  pub mean: f32,
+  /// Number of empty arrays.
+  pub empty: usize,
+  /// Number of arrays in every range of lengths, doubling from 1: `0`, `1`,
+  /// `2..=3`, `4..=7` and so on. Ranges without arrays are left out.
+  pub histogram: Vec<LengthBucket>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LengthBucket {
+  pub min: usize,
+  pub max: usize,
+  pub count: usize,
+}
+
+impl LengthStats {
+  fn new(lengths: &[usize]) -> Option<Self> {
+    let min = *lengths.iter().min()?;
+    let max = *lengths.iter().max()?;
+    let mut histogram: Vec<LengthBucket> = Vec::new();
+    for length in lengths {
+      // the largest power of two up to `length`
+      let bucket_min = match length {
+        0 => 0,
+        _ => 1 << (usize::BITS - 1 - length.leading_zeros()),
+      };
+      match histogram.iter_mut().find(|bucket| bucket.min == bucket_min) {
+        Some(bucket) => bucket.count += 1,
+        None => histogram.push(LengthBucket {
+          min: bucket_min,
+          max: (bucket_min * 2).saturating_sub(1),
+          count: 1,
+        }),
+      }
+    }
+    histogram.sort_by_key(|bucket| bucket.min);
+    Some(LengthStats {
+      min,
+      max,
+      mean: ratio(lengths.iter().sum(), lengths.len()),
+      empty: lengths.iter().filter(|length| **length == 0).count(),
+      histogram,
+    })
+  }
+}
+
+/// Arrays longer than this are never taken for tuples.
+pub static MAX_TUPLE_LENGTH: usize = 8;
+
+/// Types found at one position of arrays that look like tuples, i.e. the
+/// `Double` of `[Double, Double]` coordinates.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TuplePosition {
+  pub bson_types: Vec<String>,
+}
+
+// Types of every position of arrays, as long as they all have the same
+// length.
+#[derive(Debug, Clone, PartialEq, Default)]
+enum Positions {
+  #[default]
+  Unseen,
+  Same(Vec<Vec<String>>),
+  Differ,
+}
+
+impl Positions {
+  fn add(&mut self, arr: &[Bson]) {
+    if arr.len() < 2 || arr.len() > MAX_TUPLE_LENGTH {
+      *self = Positions::Differ;
+      return;
+    }
+    match self {
+      Positions::Unseen => {
+        let types = arr.iter().map(|val| vec![FieldType::get_type(val)]);
+        *self = Positions::Same(types.collect());
+      }
+      Positions::Same(positions) if positions.len() == arr.len() => {
+        for (types, val) in positions.iter_mut().zip(arr) {
+          let bson_type = FieldType::get_type(val);
+          if !types.contains(&bson_type) {
+            types.push(bson_type);
+            types.sort();
+          }
+        }
+      }
+      _ => *self = Positions::Differ,
+    }
+  }
+
+  // A single array is as much a list as a tuple, so it takes more than one
+  // of the same length to tell.
+  fn tuple(&self, arrays: usize) -> Option<Vec<TuplePosition>> {
+    match self {
+      Positions::Same(positions) if arrays > 1 => Some(
+        positions
+          .iter()
+          .map(|types| TuplePosition {
+            bson_types: types.clone(),
+          })
+          .collect(),
+      ),
+      _ => None,
+    }
+  }
+}
+
+/// How many `_id`s of documents are kept for every type.
+pub static MAX_DOCUMENT_IDS: usize = 10;
+
 pub static JAVASCRIPT_CODE_WITH_SCOPE: &str = "JavaScriptCodeWithScope";
 pub static JAVASCRIPT_CODE: &str = "JavaScriptCode";
 pub static FLOATING_POINT: &str = "Double";
@@ -49,7 +198,7 @@ impl FieldType {
   where
     T: Into<String>,
     U: Into<String> + Copy,
-   {
+  {
     FieldType {
       path: path.into(),
       bson_type: bson_type.into(),
@@ -61,109 +210,150 @@ impl FieldType {
       values: Vec::new(),
       has_duplicates: false,
       lengths: Vec::new(),
+      length_stats: None,
+      tuple: None,
       schema: None,
       types: HashMap::new(),
       unique: None,
+      truncated: None,
+      encrypted: None,
+      document_ids: Vec::new(),
+      positions: Positions::Unseen,
+      geo: GeoCounts::default(),
+      pii: HashMap::new(),
     }
   }
 
-  pub fn add_to_type(&mut self, value: &Bson, parent_count: usize) {
-    let bson_value = value.clone();
+  pub fn add_to_type(
+    &mut self,
+    value: &Bson,
+    parent_count: usize,
+    context: &WriteContext,
+  ) {
     self.set_probability(parent_count);
+    self.add_document_id(context);
 
     match value {
-      Bson::Array(arr) => {
-        // push items into a types array for nested documents. if current item
-        // type is a Document, create another schema parser;
-        for val in arr.iter() {
-          let current_type = Self::get_type(val);
-
-          if self.types.contains_key(&current_type) {
-            // console::log_2(&"current type contains key".into(), &current_type.clone().into());
-            // console::log_2(&"current self field_type contains key".into(), &self.path.clone().into());
-            self.types.get_mut(&current_type).unwrap().add_to_type(&val, self.count);
-          } else {
-            let mut field_type = FieldType::new(&self.path, &current_type);
-            field_type.add_to_type(&val, self.count); // this is recursive
-            self.types.insert(current_type, field_type.clone());
-          }
-          self.lengths.push(arr.len());
-          Self::get_value(&val).map(|v| self.values.push(v));
-        }
-      }
-      Bson::Document(subdoc) => {
-        match self.schema.as_mut() {
-          Some(doc) => {
-            doc.generate_field(subdoc.to_owned(), Some(self.path.clone()), Some(self.count));
-          },
-          None => {
-            let mut schema_parser = SchemaParser::new();
-            schema_parser.generate_field(
-              subdoc.to_owned(),
-              Some(self.path.clone()),
-              Some(self.count),
-            );
-            self.set_schema(schema_parser);
-          }
-        }
-      }
-      _ => {
-        Self::get_value(&bson_value).map(|v| self.values.push(v));
-      }
+      Bson::Array(arr) => self.add_elements(arr, context),
+      Bson::Document(subdoc) => self.add_to_schema(subdoc, context),
+      _ => self.add_scalar(value, context),
     }
   }
 
-  pub fn update_type(&mut self, value: &Bson) {
-    if self.bson_type == "Document" {
-      match &mut self.schema {
-        Some(schema_parser) => match &value {
-          Bson::Document(subdoc) => schema_parser.generate_field(
-            subdoc.to_owned(),
-            Some(self.path.clone()),
-            Some(self.count),
-          ),
-          _ => {
-            unimplemented!()
-          }
-        },
-        None => {
-          unimplemented!()
-        }
-      }
+  pub fn update_type(&mut self, value: &Bson, context: &WriteContext) {
+    self.add_document_id(context);
+    // only Documents are typed "Document", so anything else has no schema to
+    // update
+    if let Bson::Document(subdoc) = value {
+      self.add_to_schema(subdoc, context);
     }
 
     self.update_count();
-    self.update_value(&value);
+    self.update_value(value, context);
+  }
+
+  // Writes a subdocument into the schema of this type, creating it for the
+  // first one.
+  fn add_to_schema(&mut self, subdoc: &Document, context: &WriteContext) {
+    self.geo.add_document(subdoc);
+    let context = match context.nested() {
+      Some(context) => context,
+      None => return self.truncate(!subdoc.is_empty()),
+    };
+    let path = Some(self.path.clone());
+    let count = Some(self.count);
+    self
+      .schema
+      .get_or_insert_with(SchemaParser::new)
+      .generate_field(subdoc, path, count, &context);
   }
 
-  fn update_value(&mut self, value: &Bson) {
+  fn update_value(&mut self, value: &Bson, context: &WriteContext) {
     match value {
-      Bson::Array(arr) => {
-        for val in arr.iter() {
-          let current_type = Self::get_type(val);
-
-          if self.types.contains_key(&current_type) {
-            self.types.get_mut(&current_type).unwrap().add_to_type(&val, self.count);
-          } else {
-            let mut field_type = FieldType::new(&self.path, &current_type);
-            field_type.add_to_type(&val, self.count);
-            self.types.insert(current_type, field_type.to_owned());
-          }
-          self.lengths.push(arr.len());
-          Self::get_value(&val).map(|v| self.values.push(v));
-        }
+      Bson::Array(arr) => self.add_elements(arr, context),
+      _ => self.add_scalar(value, context),
+    }
+  }
+
+  // Writes the elements of an array into the types of this one, up to
+  // `max_array_elements` of them.
+  fn add_elements(&mut self, arr: &[Bson], context: &WriteContext) {
+    if !context.options.analyse_arrays {
+      return;
+    }
+    let context = match context.nested() {
+      Some(context) => context,
+      None => return self.truncate(!arr.is_empty()),
+    };
+    let max_elements = context.options.max_array_elements;
+    let max_elements = max_elements.unwrap_or(usize::MAX);
+    self.truncate(arr.len() > max_elements);
+    self.lengths.push(arr.len());
+    self.positions.add(arr);
+    self.geo.add_array(arr);
+    // push items into a types array for nested documents. if current item
+    // type is a Document, create another schema parser;
+    for val in arr.iter().take(max_elements) {
+      let current_type = Self::get_type(val);
+
+      if let Some(field_type) = self.types.get_mut(&current_type) {
+        field_type.update_count();
+        field_type.add_to_type(val, self.count, &context);
+      } else {
+        let mut field_type = FieldType::new(&self.path, &current_type);
+        field_type.add_to_type(val, self.count, &context); // this is recursive
+        self.types.insert(current_type, field_type);
       }
-      _ => {
-        Self::get_value(&value).map(|v| self.values.push(v));
+      self.add_value(val, &context);
+    }
+  }
+
+  // Counts a value that was cut short by one of the limits of the options.
+  fn truncate(&mut self, truncated: bool) {
+    if truncated {
+      *self.truncated.get_or_insert(0) += 1;
+    }
+  }
+
+  // Counts a value of this type as personal data if it looks like it, or as
+  // encrypted, and keeps it. Arrays keep the values of their elements too, which are
+  // counted in the types of the elements only.
+  fn add_scalar(&mut self, value: &Bson, context: &WriteContext) {
+    if let Some(kind) = pii::detect(value) {
+      *self.pii.entry(kind).or_insert(0) += 1;
+    }
+    if let Bson::Binary(binary) = value {
+      if binary.subtype == BinarySubtype::Encrypted {
+        *self.encrypted.get_or_insert(0) += 1;
       }
     }
+    self.add_value(value, context);
+  }
+
+  // Keeps the value, redacted if need be, unless `max_values` are kept
+  // already.
+  // `Option::is_none_or` needs Rust 1.82
+  #[allow(clippy::unnecessary_map_or)]
+  fn add_value(&mut self, value: &Bson, context: &WriteContext) {
+    let options = context.options;
+    if options
+      .max_values
+      .map_or(true, |max| self.values.len() < max)
+    {
+      Self::get_value(value)
+        .and_then(|v| redact(options, &self.path, value, v))
+        .map(|v| self.values.push(v));
+    }
   }
 
   pub fn get_value(value: &Bson) -> Option<ValueType> {
     match value {
-      Bson::JavaScriptCode(val)
-      | Bson::Symbol(val) => Some(ValueType::Str(val.to_string())),
-      Bson::JavaScriptCodeWithScope(val) => Some(ValueType::Str(val.to_string())),
+      Bson::JavaScriptCode(val) | Bson::Symbol(val) => {
+        Some(ValueType::Str(val.to_string()))
+      }
+      Bson::JavaScriptCodeWithScope(val) => {
+        Some(ValueType::Str(val.to_string()))
+      }
       Bson::Int32(num) => Some(ValueType::I32(*num)),
       Bson::Int64(num) => Some(ValueType::I64(*num)),
       Bson::Timestamp(num) => Some(ValueType::I32(num.time as i32)),
@@ -181,22 +371,47 @@ impl FieldType {
     }
   }
 
-  pub fn finalise_type(&mut self, parent_count: usize) {
+  pub fn finalise_type(
+    &mut self,
+    parent_count: usize,
+    options: &SchemaParserOptions,
+  ) {
+    // element types are a share of the elements of every array, rather than
+    // of the arrays
+    let elements = self.types.values().map(|field| field.count).sum();
     if !self.types.is_empty() {
       for (key, field) in self.types.iter_mut() {
         if key == "Document" {
           // TODO: get option without unwrap
           let schema = &mut field.schema;
           if let Some(schema) = schema {
-            schema.finalise_schema();
+            schema.finalise_with(options);
           }
         }
-        field.finalise_type(self.count);
+        field.finalise_type(elements, options);
       }
     }
     self.set_probability(parent_count);
-    self.set_unique();
-    self.set_duplicates();
+    if self.bson_type == ARRAY {
+      self.length_stats = LengthStats::new(&self.lengths);
+      self.tuple = self.positions.tuple(self.count);
+    }
+    let dropped =
+      options.redaction == Redaction::Drop && is_redacted(options, &self.path);
+    if options.unique && !dropped {
+      self.set_unique();
+      self.set_duplicates();
+    }
+  }
+
+  /// Returns the schema of this type if it is a Document, and the schemas of
+  /// any documents nested in it if it is an Array.
+  pub fn schemas(&self) -> Vec<&SchemaParser> {
+    let mut schemas: Vec<&SchemaParser> = self.schema.iter().collect();
+    for field_type in self.types.values() {
+      schemas.extend(field_type.schemas());
+    }
+    schemas
   }
 
   pub fn get_type(value: &Bson) -> String {
@@ -233,10 +448,11 @@ impl FieldType {
     (total_values - unique) != 0
   }
 
-  fn get_unique(&mut self) -> usize {
+  pub(crate) fn get_unique(&self) -> usize {
     let mut vec = self.values.clone();
-    vec.sort_by(|a, b| a.partial_cmp(b).unwrap());
-    vec.dedup();
+    // NaN doubles can't be compared with `partial_cmp`
+    vec.sort_by(ValueType::total_cmp);
+    vec.dedup_by(|a, b| a.total_cmp(b) == Ordering::Equal);
     vec.len()
   }
 
@@ -254,8 +470,15 @@ impl FieldType {
     self.has_duplicates = duplicates
   }
 
-  fn set_schema(&mut self, schema: SchemaParser) {
-    self.schema = Some(schema)
+  fn add_document_id(&mut self, context: &WriteContext) {
+    if let Some(id) = &context.document_id {
+      // values inside the same array share a document
+      if self.document_ids.len() < MAX_DOCUMENT_IDS
+        && self.document_ids.last() != Some(id)
+      {
+        self.document_ids.push(id.clone());
+      }
+    }
   }
 
   fn set_unique(&mut self) {
@@ -340,16 +563,14 @@ mod tests {
   #[allow(clippy::float_cmp)]
   #[test]
   fn it_sets_probability() {
-    let mut field_type =
-      FieldType::new("address", "Oranienstr. 123");
+    let mut field_type = FieldType::new("address", "Oranienstr. 123");
     field_type.set_probability(10);
     assert_eq!(field_type.probability, 0.1);
   }
 
   #[test]
   fn it_gets_unique() {
-    let mut field_type =
-      FieldType::new("address", "Oranienstr. 123");
+    let mut field_type = FieldType::new("address", "Oranienstr. 123");
     field_type.values.push(ValueType::Str("Berlin".to_string()));
     field_type
       .values
@@ -371,8 +592,7 @@ mod tests {
 
   #[test]
   fn it_sets_unique() {
-    let mut field_type =
-      FieldType::new("address", "Oranienstr. 123");
+    let mut field_type = FieldType::new("address", "Oranienstr. 123");
     field_type.values.push(ValueType::Str("Berlin".to_string()));
     field_type
       .values
@@ -394,24 +614,22 @@ mod tests {
 
   #[test]
   fn it_gets_duplicates_when_none() {
-    let mut field_type =
-      FieldType::new("address", "Oranienstr. 123");
+    let mut field_type = FieldType::new("address", "Oranienstr. 123");
     field_type.values.push(ValueType::Str("Berlin".to_string()));
     field_type
       .values
       .push(ValueType::Str("Hamburg".to_string()));
     let has_duplicates = field_type.get_duplicates();
-    assert_eq!(has_duplicates, false)
+    assert!(!has_duplicates)
   }
 
   #[test]
   fn it_gets_duplicates_when_some() {
-    let mut field_type =
-      FieldType::new("address", "Oranienstr. 123");
+    let mut field_type = FieldType::new("address", "Oranienstr. 123");
     field_type.values.push(ValueType::Str("Berlin".to_string()));
     field_type.values.push(ValueType::Str("Berlin".to_string()));
     let has_duplicates = field_type.get_duplicates();
-    assert_eq!(has_duplicates, true)
+    assert!(has_duplicates)
   }
 
   // #[bench]
@@ -427,12 +645,11 @@ mod tests {
 
   #[test]
   fn it_sets_duplicates() {
-    let mut field_type =
-      FieldType::new("address", "Oranienstr. 123");
+    let mut field_type = FieldType::new("address", "Oranienstr. 123");
     field_type.values.push(ValueType::Str("Berlin".to_string()));
     field_type.values.push(ValueType::Str("Berlin".to_string()));
     field_type.set_duplicates();
-    assert_eq!(field_type.has_duplicates, true)
+    assert!(field_type.has_duplicates)
   }
 
   // #[bench]
@@ -444,8 +661,7 @@ mod tests {
 
   #[test]
   fn it_updates_count() {
-    let mut field_type =
-      FieldType::new("address", "Oranienstr. 123");
+    let mut field_type = FieldType::new("address", "Oranienstr. 123");
     field_type.update_count();
     assert_eq!(field_type.count, 2);
   }
@@ -460,9 +676,8 @@ mod tests {
   #[test]
   fn it_updates_value_some() {
     let bson_value = Bson::Int32(1234);
-    let mut field_type =
-      FieldType::new("address", "Oranienstr. 123");
-    field_type.update_value(&bson_value);
+    let mut field_type = FieldType::new("address", "Oranienstr. 123");
+    field_type.update_value(&bson_value, &WriteContext::default());
     assert_eq!(field_type.values[0], ValueType::I32(1234));
   }
 
@@ -0,0 +1,358 @@
+use super::field_type::{ARRAY, DOCUMENT};
+use super::redact::normalise_name;
+use super::{Bson, Document, Field, FieldType};
+
+// GeoJSON geometry types with `coordinates`; `GeometryCollection` has
+// `geometries` instead.
+static GEOMETRY_TYPES: &[&str] = &[
+  "Point",
+  "MultiPoint",
+  "LineString",
+  "MultiLineString",
+  "Polygon",
+  "MultiPolygon",
+];
+static GEOMETRY_COLLECTION: &str = "GeometryCollection";
+
+// Field names that suggest an array of two numbers is a legacy coordinate
+// pair rather than, say, a score. Compared like `SENSITIVE_NAMES`.
+static LOCATION_NAMES: &[&str] = &[
+  "loc", "location", "coords", "geo", "point", "position", "latlng", "lnglat",
+  "lonlat", "center",
+];
+
+/// A field holding locations, as found by `SchemaParser::flush`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Geospatial {
+  pub format: GeoFormat,
+  /// GeoJSON geometry types found, i.e. `Point`.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub geometry_types: Vec<String>,
+  /// Values with coordinates out of range, or malformed GeoJSON, which an
+  /// index would reject.
+  pub invalid: usize,
+  /// Index for geospatial queries on this field.
+  pub index: GeoIndex,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum GeoFormat {
+  /// Documents like `{type: "Point", coordinates: [lng, lat]}`.
+  GeoJson,
+  /// Arrays like `[lng, lat]`.
+  Legacy,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum GeoIndex {
+  /// For GeoJSON on a sphere.
+  #[serde(rename = "2dsphere")]
+  TwoDSphere,
+  /// For legacy coordinate pairs on a flat plane.
+  #[serde(rename = "2d")]
+  TwoD,
+}
+
+// Locations found in the values of a type while they are written.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub(crate) struct GeoCounts {
+  // documents that are GeoJSON geometries, and those that are invalid
+  geometries: usize,
+  invalid_geometries: usize,
+  geometry_types: Vec<String>,
+  // arrays of two numbers, and those out of range
+  pairs: usize,
+  invalid_pairs: usize,
+}
+
+impl GeoCounts {
+  pub(crate) fn add_document(&mut self, doc: &Document) {
+    let geometry_type = match doc.get_str("type") {
+      Ok(geometry_type) if is_geometry_type(geometry_type) => geometry_type,
+      _ => return,
+    };
+    self.geometries += 1;
+    if !is_geometry(doc) {
+      self.invalid_geometries += 1;
+    }
+    if !self.geometry_types.iter().any(|t| t == geometry_type) {
+      self.geometry_types.push(geometry_type.to_string());
+      self.geometry_types.sort();
+    }
+  }
+
+  pub(crate) fn add_array(&mut self, arr: &[Bson]) {
+    if let [lng, lat] = arr {
+      if let (Some(lng), Some(lat)) = (number(lng), number(lat)) {
+        self.pairs += 1;
+        if !in_2d_range(lng, lat) {
+          self.invalid_pairs += 1;
+        }
+      }
+    }
+  }
+
+  fn merge(&mut self, other: &GeoCounts) {
+    self.geometries += other.geometries;
+    self.invalid_geometries += other.invalid_geometries;
+    for geometry_type in &other.geometry_types {
+      if !self.geometry_types.contains(geometry_type) {
+        self.geometry_types.push(geometry_type.to_string());
+      }
+    }
+    self.geometry_types.sort();
+  }
+}
+
+// A field is GeoJSON if all its documents, including those inside arrays,
+// are geometries, and legacy coordinates if it has a location-like name and
+// all its arrays are pairs of numbers.
+pub(crate) fn geospatial(field: &Field) -> Option<Geospatial> {
+  let mut documents = 0;
+  let mut counts = GeoCounts::default();
+  for field_type in field.types.values() {
+    add_documents(field_type, &mut documents, &mut counts);
+  }
+  if counts.geometries > 0 && counts.geometries == documents {
+    return Some(Geospatial {
+      format: GeoFormat::GeoJson,
+      geometry_types: counts.geometry_types,
+      invalid: counts.invalid_geometries,
+      index: GeoIndex::TwoDSphere,
+    });
+  }
+
+  let arrays = field.types.get(ARRAY)?;
+  let pairs = &arrays.geo;
+  if pairs.pairs > 0 && pairs.pairs == arrays.count && is_location(&field.name)
+  {
+    return Some(Geospatial {
+      format: GeoFormat::Legacy,
+      geometry_types: Vec::new(),
+      invalid: pairs.invalid_pairs,
+      index: GeoIndex::TwoD,
+    });
+  }
+  None
+}
+
+fn add_documents(
+  field_type: &FieldType,
+  documents: &mut usize,
+  counts: &mut GeoCounts,
+) {
+  if field_type.bson_type == DOCUMENT {
+    *documents += field_type.count;
+    counts.merge(&field_type.geo);
+  }
+  for element_type in field_type.types.values() {
+    add_documents(element_type, documents, counts);
+  }
+}
+
+fn is_location(name: &str) -> bool {
+  let name = normalise_name(name);
+  LOCATION_NAMES
+    .iter()
+    .any(|location| name.ends_with(location))
+}
+
+fn is_geometry_type(geometry_type: &str) -> bool {
+  geometry_type == GEOMETRY_COLLECTION
+    || GEOMETRY_TYPES.contains(&geometry_type)
+}
+
+// Whether `doc` is a valid GeoJSON geometry, as `2dsphere` indexes expect.
+fn is_geometry(doc: &Document) -> bool {
+  let geometry_type = match doc.get_str("type") {
+    Ok(geometry_type) => geometry_type,
+    Err(_) => return false,
+  };
+  if geometry_type == GEOMETRY_COLLECTION {
+    return match doc.get_array("geometries") {
+      Ok(geometries) => geometries.iter().all(|geometry| match geometry {
+        Bson::Document(geometry) => is_geometry(geometry),
+        _ => false,
+      }),
+      Err(_) => false,
+    };
+  }
+  let coordinates = match doc.get_array("coordinates") {
+    Ok(coordinates) => coordinates,
+    Err(_) => return false,
+  };
+  match geometry_type {
+    "Point" => is_position(coordinates),
+    "MultiPoint" => all(coordinates, is_position),
+    "LineString" => is_line(coordinates),
+    "MultiLineString" => all(coordinates, is_line),
+    "Polygon" => is_polygon(coordinates),
+    "MultiPolygon" => all(coordinates, is_polygon),
+    _ => false,
+  }
+}
+
+// Whether every element of `arr` is an array that `f` accepts.
+fn all(arr: &[Bson], f: fn(&[Bson]) -> bool) -> bool {
+  arr.iter().all(|value| match value {
+    Bson::Array(value) => f(value),
+    _ => false,
+  })
+}
+
+fn is_line(positions: &[Bson]) -> bool {
+  positions.len() > 1 && all(positions, is_position)
+}
+
+// Rings have at least four positions and end where they start. Positions are
+// compared as numbers, so that `[0, 0]` and `[0.0, 0.0]` are the same.
+fn is_polygon(rings: &[Bson]) -> bool {
+  let is_ring = |ring: &[Bson]| {
+    ring.len() > 3
+      && all(ring, is_position)
+      && ring.first().and_then(coordinates) == ring.last().and_then(coordinates)
+  };
+  !rings.is_empty()
+    && rings.iter().all(|ring| match ring {
+      Bson::Array(ring) => is_ring(ring),
+      _ => false,
+    })
+}
+
+// `[lng, lat]`, with an optional altitude.
+fn is_position(position: &[Bson]) -> bool {
+  let numbers: Option<Vec<f64>> = position.iter().map(number).collect();
+  match numbers.as_deref() {
+    Some([lng, lat]) | Some([lng, lat, _]) => in_sphere_range(*lng, *lat),
+    _ => false,
+  }
+}
+
+// Bounds of GeoJSON positions in `2dsphere` indexes.
+fn in_sphere_range(lng: f64, lat: f64) -> bool {
+  (-180.0..=180.0).contains(&lng) && (-90.0..=90.0).contains(&lat)
+}
+
+// Default bounds of `2d` indexes, the same for both coordinates and without
+// the upper one.
+fn in_2d_range(x: f64, y: f64) -> bool {
+  (-180.0..180.0).contains(&x) && (-180.0..180.0).contains(&y)
+}
+
+fn coordinates(position: &Bson) -> Option<Vec<f64>> {
+  match position {
+    Bson::Array(position) => position.iter().map(number).collect(),
+    _ => None,
+  }
+}
+
+fn number(value: &Bson) -> Option<f64> {
+  match value {
+    Bson::Double(num) => Some(*num),
+    Bson::Int32(num) => Some(f64::from(*num)),
+    Bson::Int64(num) => Some(*num as f64),
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::SchemaParser;
+  use bson::doc;
+
+  #[test]
+  fn it_validates_geometries() {
+    assert!(is_geometry(
+      &doc! {"type": "Point", "coordinates": [13.4, 52]}
+    ));
+    assert!(!is_geometry(
+      &doc! {"type": "Point", "coordinates": [52, 91]}
+    ));
+    assert!(!is_geometry(&doc! {"type": "Point", "coordinates": [1]}));
+    let point = |lng: f64, lat: f64| {
+      is_geometry(&doc! {"type": "Point", "coordinates": [lng, lat]})
+    };
+    assert!(point(180.0, 90.0));
+    assert!(point(-180.0, -90.0));
+    assert!(!point(180.5, 0.0));
+    assert!(!point(0.0, -90.5));
+    let square = doc! {
+      "type": "Polygon",
+      "coordinates": [[[0, 0], [1, 0], [1, 1], [0, 0]]],
+    };
+    assert!(is_geometry(&square));
+    let open = doc! {
+      "type": "Polygon",
+      "coordinates": [[[0, 0], [1, 0], [1, 1], [0, 1]]],
+    };
+    assert!(!is_geometry(&open));
+    let mixed = doc! {
+      "type": "Polygon",
+      "coordinates": [[[0, 0], [1, 0], [1, 1], [0.0, 0.0]]],
+    };
+    assert!(is_geometry(&mixed));
+    let collection = doc! {
+      "type": "GeometryCollection",
+      "geometries": [
+        {"type": "LineString", "coordinates": [[0, 0], [1, 1]]},
+        square,
+      ],
+    };
+    assert!(is_geometry(&collection));
+  }
+
+  #[test]
+  fn it_finds_geospatial_fields() {
+    let mut schema_parser = SchemaParser::new();
+    for (lng, lat) in &[(13.4, 52.5), (-73.9, 40.7), (200.0, 40.7)] {
+      schema_parser
+        .write_bson(doc! {
+          "location": {"type": "Point", "coordinates": [lng, lat]},
+          "homeLoc": [lng, lat],
+          "scores": [lng, lat],
+          "shape": {"type": "Square"},
+        })
+        .unwrap();
+    }
+    let schema = schema_parser.flush();
+    let location = schema.fields["location"].geo.as_ref().unwrap();
+    assert_eq!(location.format, GeoFormat::GeoJson);
+    assert_eq!(location.geometry_types, vec!["Point"]);
+    assert_eq!(location.invalid, 1);
+    assert_eq!(location.index, GeoIndex::TwoDSphere);
+
+    let home = schema.fields["homeLoc"].geo.as_ref().unwrap();
+    assert_eq!(home.format, GeoFormat::Legacy);
+    assert_eq!(home.invalid, 1);
+    assert_eq!(home.index, GeoIndex::TwoD);
+    let json = serde_json::to_value(home).unwrap();
+    assert_eq!(json["index"], "2d");
+
+    assert_eq!(schema.fields["scores"].geo, None);
+    assert_eq!(schema.fields["shape"].geo, None);
+    // the coordinates of GeoJSON are not legacy pairs
+    let point = &schema.fields["location"].types["Document"];
+    let point = point.schema.as_ref().unwrap();
+    assert_eq!(point.fields["coordinates"].geo, None);
+  }
+
+  #[test]
+  fn it_checks_legacy_pairs_against_2d_bounds() {
+    let invalid = |pairs: &[(f64, f64)]| {
+      let mut counts = GeoCounts::default();
+      for (x, y) in pairs {
+        counts.add_array(&[Bson::Double(*x), Bson::Double(*y)]);
+      }
+      counts.invalid_pairs
+    };
+    assert_eq!(invalid(&[(-180.0, -180.0), (179.9, 179.9)]), 0);
+    // unlike GeoJSON, the second coordinate can go past 90
+    assert_eq!(invalid(&[(0.0, 120.0)]), 0);
+    // and neither can be 180
+    assert_eq!(invalid(&[(180.0, 0.0)]), 1);
+    assert_eq!(invalid(&[(0.0, 180.0)]), 1);
+    assert_eq!(invalid(&[(-180.5, 0.0)]), 1);
+  }
+}
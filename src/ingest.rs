@@ -0,0 +1,175 @@
+use super::{Error, ErrorKind, Result};
+use std::fmt::{self, Display};
+
+/// How many skipped inputs are kept in an `IngestReport`. Any further ones
+/// are only counted.
+pub static MAX_INGEST_ERRORS: usize = 100;
+
+/// How many characters of a skipped input are kept as its snippet.
+pub static SNIPPET_LENGTH: usize = 80;
+
+/// Inputs that were skipped in lenient mode, as returned by
+/// `SchemaParser::ingest_report`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct IngestReport {
+  /// Number of skipped inputs, including the ones not kept in `errors`.
+  pub skipped: usize,
+  /// The first `MAX_INGEST_ERRORS` skipped inputs.
+  pub errors: Vec<IngestError>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IngestError {
+  pub location: Location,
+  pub kind: IngestErrorKind,
+  /// The error that would have been returned outside of lenient mode.
+  pub message: String,
+  /// The start of the skipped input. BSON is shown as hex.
+  pub snippet: String,
+}
+
+/// Where a skipped input was found.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Location {
+  /// Line of a newline-delimited json or CSV input, starting at 1.
+  Line(u64),
+  /// Byte offset of a BSON document.
+  Offset(u64),
+  /// Index of an element of a json array.
+  Element(usize),
+  /// Index of a document passed to `write_json` or `write_raw`, counting
+  /// skipped ones.
+  Document(usize),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum IngestErrorKind {
+  /// Malformed json or CSV.
+  Syntax,
+  /// Well-formed, but not a document, i.e. a json array or number.
+  NotADocument,
+  /// A value that can't be converted to BSON, i.e. `{"$oid": 1}` or `two`
+  /// in a `int32()` CSV column.
+  InvalidValue,
+  /// A BSON document with a valid length but malformed contents.
+  InvalidBson,
+  /// A document going past one of the limits of `SchemaParserOptions`.
+  LimitExceeded,
+}
+
+impl IngestErrorKind {
+  fn of(error: &Error) -> Self {
+    match error.kind() {
+      ErrorKind::Json(_) => IngestErrorKind::Syntax,
+      #[cfg(feature = "csv")]
+      ErrorKind::Csv(_) => IngestErrorKind::Syntax,
+      ErrorKind::NotADocument(_) => IngestErrorKind::NotADocument,
+      ErrorKind::Bson(_) => IngestErrorKind::InvalidBson,
+      ErrorKind::LimitExceeded { .. } => IngestErrorKind::LimitExceeded,
+      _ => IngestErrorKind::InvalidValue,
+    }
+  }
+}
+
+impl IngestReport {
+  /// Returns `true` if nothing was skipped.
+  pub fn is_empty(&self) -> bool {
+    self.skipped == 0
+  }
+
+  /// Returns the report as a serde_json string.
+  pub fn to_json(&self) -> Result<String> {
+    Ok(serde_json::to_string(self)?)
+  }
+
+  // `error` needs to know its location.
+  pub(crate) fn push(&mut self, error: &Error, snippet: &str) {
+    self.skipped += 1;
+    if self.errors.len() < MAX_INGEST_ERRORS {
+      self.errors.push(IngestError {
+        location: error.location().unwrap_or(Location::Document(0)),
+        kind: IngestErrorKind::of(error),
+        message: error.to_string(),
+        snippet: snippet.chars().take(SNIPPET_LENGTH).collect(),
+      });
+    }
+  }
+}
+
+impl Display for Location {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Location::Line(line) => write!(f, "line {}", line),
+      Location::Offset(offset) => write!(f, "byte offset {}", offset),
+      Location::Element(index) => write!(f, "array element {}", index),
+      Location::Document(index) => write!(f, "document {}", index),
+    }
+  }
+}
+
+impl Display for IngestReport {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.is_empty() {
+      return writeln!(f, "nothing skipped");
+    }
+    for error in &self.errors {
+      writeln!(f, "{}", error.message)?;
+      if !error.snippet.is_empty() {
+        writeln!(f, "  {}", error.snippet)?;
+      }
+    }
+    if self.skipped > self.errors.len() {
+      writeln!(f, "... and {} more", self.skipped - self.errors.len())?;
+    }
+    Ok(())
+  }
+}
+
+// Snippet of binary input.
+pub(crate) fn hex(bytes: &[u8]) -> String {
+  bytes
+    .iter()
+    .take(SNIPPET_LENGTH / 2)
+    .map(|byte| format!("{:02x}", byte))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_keeps_the_first_errors() {
+    let mut report = IngestReport::default();
+    for line in 0..MAX_INGEST_ERRORS as u64 + 5 {
+      let message = "'two' is not a valid int32".to_string();
+      let error = Error::from(ErrorKind::InvalidValue(message))
+        .at(Location::Line(line + 1));
+      report.push(&error, &"{".repeat(100));
+    }
+    assert_eq!(report.skipped, MAX_INGEST_ERRORS + 5);
+    assert_eq!(report.errors.len(), MAX_INGEST_ERRORS);
+    assert_eq!(report.errors[0].snippet.len(), SNIPPET_LENGTH);
+    assert!(report.to_string().ends_with("... and 5 more\n"));
+  }
+
+  #[test]
+  fn it_serializes_locations() {
+    let mut report = IngestReport::default();
+    let bytes = [0x05, 0x00, 0x00, 0x00, 0x01];
+    let error = bson::Document::from_reader(&bytes[..]).unwrap_err();
+    let error = Error::from(error).at(Location::Offset(12));
+    report.push(&error, &hex(&bytes));
+    let json: serde_json::Value =
+      serde_json::from_str(&report.to_json().unwrap()).unwrap();
+    assert_eq!(json["skipped"], 1);
+    assert_eq!(
+      json["errors"][0]["location"],
+      serde_json::json!({"offset": 12})
+    );
+    assert_eq!(json["errors"][0]["kind"], "invalid_bson");
+    assert_eq!(json["errors"][0]["snippet"], "0500000001");
+  }
+}
@@ -44,15 +44,10 @@
 //!   })
 //! ```
 
-#![cfg_attr(feature = "nightly", deny(missing_docs))]
-#![cfg_attr(feature = "nightly", feature(external_doc))]
-#![cfg_attr(feature = "nightly", doc(include = "../README.md"))]
-#![cfg_attr(feature = "nightly", deny(unsafe_code))]
 #![allow(unused_imports)]
 #![allow(clippy::new_without_default)]
 // #![feature(test)]
 
-use failure::format_err;
 // extern crate test;
 
 use bson::{bson, doc, to_bson, Bson, Document};
@@ -69,11 +64,11 @@ use web_sys::console;
 
 // using custom allocator which is built specifically for wasm; makes it smaller
 // + faster
-use wee_alloc;
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::string::String;
 
 mod field;
@@ -85,15 +80,147 @@ use crate::field_type::FieldType;
 mod value_type;
 use crate::value_type::ValueType;
 
+mod diff;
+pub use crate::diff::{
+  ProbabilityChange, SchemaDiff, TypeChange, DEFAULT_PROBABILITY_THRESHOLD,
+};
+
+mod drift;
+pub use crate::drift::{Drift, DriftOptions, DriftReport};
+
+mod check;
+pub use crate::check::{CheckOptions, Deviation};
+
+mod anomaly;
+pub use crate::anomaly::{
+  Anomaly, AnomalyReport, DEFAULT_ANOMALY_THRESHOLD, MAX_EXAMPLES,
+};
+
+mod compass;
+pub use crate::compass::{
+  type_name as compass_type_name, CompassField, CompassSchema, CompassType,
+  CompassTypeName,
+};
+
+mod reader;
+use crate::reader::{
+  decode, decompress, invalid_length, starts_with_array, Block, BsonReader,
+  JsonArrayVisitor,
+};
+
+mod archive;
+
+mod error;
+pub use crate::error::{Error, ErrorKind, Result};
+
+mod ingest;
+use crate::ingest::hex;
+pub use crate::ingest::{
+  IngestError, IngestErrorKind, IngestReport, Location, MAX_INGEST_ERRORS,
+  SNIPPET_LENGTH,
+};
+
+mod options;
+pub use crate::options::{OnLimit, SchemaParserOptions};
+
+mod projection;
+
+mod limits;
+
+mod redact;
+pub use crate::redact::{Redaction, SENSITIVE_NAMES};
+
+mod pii;
+pub use crate::pii::{PiiField, PiiKind, PiiMatch, PiiReport};
+
+mod geo;
+pub use crate::geo::{GeoFormat, GeoIndex, Geospatial};
+
+mod encryption;
+pub use crate::encryption::{
+  EncryptedField, EncryptionAlgorithm, EncryptionReport, EncryptionSuggestion,
+  DEFAULT_CARDINALITY_THRESHOLD,
+};
+
+#[cfg(feature = "csv")]
+mod delimited;
+#[cfg(feature = "csv")]
+pub use crate::delimited::CsvOptions;
+
 // WASM Api of the Schema Parser.
 mod lib_wasm;
 use crate::lib_wasm::*;
 
+// State of the document currently being written, handed down to the fields
+// and nested schemas it touches.
+#[derive(Debug)]
+pub(crate) struct WriteContext<'a> {
+  // `_id` of the top level document, if it has one.
+  pub document_id: Option<ValueType>,
+  pub options: &'a SchemaParserOptions,
+  // nesting level of the values being written; top level fields are at 1
+  pub depth: usize,
+}
+
+impl<'a> WriteContext<'a> {
+  fn new(doc: &Document, options: &'a SchemaParserOptions) -> Self {
+    WriteContext {
+      document_id: doc.get("_id").and_then(|id| {
+        FieldType::get_value(id)
+          .and_then(|kept| redact::redact(options, "_id", id, kept))
+      }),
+      options,
+      depth: 1,
+    }
+  }
+
+  // Context for the fields of a subdocument or the elements of an array
+  // found at this depth, or `None` if it is too deep to analyse.
+  // `Option::is_some_and` needs Rust 1.70
+  #[allow(clippy::unnecessary_map_or)]
+  pub fn nested(&self) -> Option<WriteContext<'a>> {
+    if self
+      .options
+      .max_depth
+      .map_or(false, |max| self.depth >= max)
+    {
+      return None;
+    }
+    Some(WriteContext {
+      document_id: self.document_id.clone(),
+      options: self.options,
+      depth: self.depth + 1,
+    })
+  }
+}
+
+static DEFAULT_OPTIONS: SchemaParserOptions = SchemaParserOptions::DEFAULT;
+
+impl Default for WriteContext<'_> {
+  fn default() -> Self {
+    WriteContext {
+      document_id: None,
+      options: &DEFAULT_OPTIONS,
+      depth: 1,
+    }
+  }
+}
+
 #[wasm_bindgen]
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct SchemaParser {
   pub count: usize,
   fields: HashMap<String, Field>,
+  // documents with fields left out by `max_fields` or `max_document_bytes`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  truncated_documents: Option<usize>,
+  // skip malformed input instead of returning an error
+  #[serde(skip)]
+  lenient: bool,
+  #[serde(skip)]
+  ingest_report: IngestReport,
+  #[serde(skip)]
+  options: SchemaParserOptions,
 }
 
 impl SchemaParser {
@@ -110,7 +237,81 @@ impl SchemaParser {
     SchemaParser {
       count: 0,
       fields: HashMap::new(),
+      truncated_documents: None,
+      lenient: false,
+      ingest_report: IngestReport::default(),
+      options: SchemaParserOptions::default(),
+    }
+  }
+
+  /// Returns a new instance of Schema Parser that analyses documents as set
+  /// out in `options`, or an `ErrorKind::InvalidOptions` error for
+  /// `Redaction::Hash` with an empty `salt`.
+  ///
+  /// # Examples
+  /// ```
+  /// use mongodb_schema_parser::{SchemaParser, SchemaParserOptions};
+  ///
+  /// let options = SchemaParserOptions {
+  ///   max_values: Some(0),
+  ///   max_depth: Some(1),
+  ///   ..Default::default()
+  /// };
+  /// let mut schema_parser = SchemaParser::with_options(options).unwrap();
+  /// let json = r#"{ "name": "Chashu", "owner": { "name": "Irina" } }"#;
+  /// schema_parser.write_json(json).unwrap();
+  /// ```
+  pub fn with_options(options: SchemaParserOptions) -> Result<Self> {
+    options.check()?;
+    Ok(SchemaParser {
+      options,
+      ..SchemaParser::new()
+    })
+  }
+
+  /// Returns the options this parser was created with.
+  pub fn options(&self) -> &SchemaParserOptions {
+    &self.options
+  }
+
+  /// In lenient mode, malformed json lines, array elements, CSV rows and
+  /// BSON documents are skipped instead of returning an error, and recorded
+  /// in `ingest_report`. Input that can't be read past, i.e. a truncated BSON
+  /// document, is still an error.
+  ///
+  /// # Examples
+  /// ```
+  /// use mongodb_schema_parser::SchemaParser;
+  ///
+  /// let mut schema_parser = SchemaParser::new();
+  /// schema_parser.set_lenient(true);
+  /// let json = "{ \"name\": \"Chashu\" }\n{ \"name\": \n";
+  /// let count = schema_parser.write_json_reader(json.as_bytes()).unwrap();
+  /// assert_eq!(count, 1);
+  /// assert_eq!(schema_parser.ingest_report().skipped, 1);
+  /// ```
+  pub fn set_lenient(&mut self, lenient: bool) {
+    self.lenient = lenient;
+  }
+
+  /// Returns the inputs skipped in lenient mode.
+  pub fn ingest_report(&self) -> &IngestReport {
+    &self.ingest_report
+  }
+
+  // Records `error` and swallows it in lenient mode. `error` needs to know
+  // its location.
+  pub(crate) fn skip(&mut self, error: Error, snippet: &str) -> Result<()> {
+    if !self.lenient {
+      return Err(error);
     }
+    self.ingest_report.push(&error, snippet);
+    Ok(())
+  }
+
+  // Index of the next document passed to `write_json` or `write_raw`.
+  fn next_document(&self) -> Location {
+    Location::Document(self.count + self.ingest_report.skipped)
   }
 
   /// Writes json-like string slices SchemaParser's fields vector.
@@ -126,16 +327,18 @@ impl SchemaParser {
   /// schema_parser.write_json(&json);
   /// ```
   #[inline]
-  pub fn write_json(&mut self, json: &str) -> Result<(), failure::Error> {
-    let val: Value = serde_json::from_str(json)?;
-    let bson = to_bson(&val)?;
-    // should do a match for NoneError
-    let doc = bson
-      .as_document()
-      .ok_or_else(|| format_err!("Failed to parse bson"))?
-      .to_owned();
-    self.update_count();
-    self.generate_field(doc, None, None);
+  pub fn write_json(&mut self, json: &str) -> Result<()> {
+    let doc = match Self::parse_json(json) {
+      Ok(doc) => doc,
+      Err(e) => {
+        let e = e.at(self.next_document());
+        return self.skip(e, json);
+      }
+    };
+    if let Err(e) = self.write_document(doc) {
+      let e = e.at(self.next_document());
+      return self.skip(e, json);
+    }
     Ok(())
   }
 
@@ -155,16 +358,23 @@ impl SchemaParser {
   /// schema_parser.write_raw(uint8);
   /// ```
   #[inline]
-  pub fn write_raw(&mut self, uint8: Uint8Array) -> Result<(), failure::Error> {
+  pub fn write_raw(&mut self, uint8: Uint8Array) -> Result<()> {
     let mut decoded_vec = vec![0u8; uint8.length() as usize];
     // fill up a new u8 vec with bytes we get from js; decode_document needs a
     // byte stream that implements a reader and u8 slice does this.
     uint8.copy_to(&mut decoded_vec);
-    let mut slice: &[u8] = &decoded_vec;
-    let doc = Document::from_reader(&mut slice)?.to_owned();
+    let doc = match decode(&decoded_vec) {
+      Ok(doc) => doc,
+      Err(e) => {
+        let e = Error::from(e).at(self.next_document());
+        return self.skip(e, &hex(&decoded_vec));
+      }
+    };
     // write bson internally
-    self.update_count();
-    self.generate_field(doc, None, None);
+    if let Err(e) = self.write_document(doc) {
+      let e = e.at(self.next_document());
+      return self.skip(e, &hex(&decoded_vec));
+    }
 
     Ok(())
   }
@@ -184,12 +394,280 @@ impl SchemaParser {
   /// schema_parser.write_bson(doc);
   /// ```
   #[inline]
-  pub fn write_bson(&mut self, doc: Document) -> Result<(), failure::Error> {
+  pub fn write_bson(&mut self, doc: Document) -> Result<()> {
     // write bson internally
-    self.update_count();
-    self.generate_field(doc, None, None);
+    self.write_document(doc)
+  }
 
-    Ok(())
+  /// Writes every document of a stream of concatenated Bson documents, which
+  /// is the format `mongodump` uses for a collection's `.bson` file. Returns
+  /// the number of documents written.
+  ///
+  /// Documents are written as they are read, so the ones before a malformed
+  /// or truncated document are kept. The error names the byte offset that
+  /// document starts at, in the decompressed input. Documents nested more
+  /// than 100 levels deep are malformed.
+  ///
+  /// # Arguments
+  /// * `reader` - Anything implementing `std::io::Read`, i.e. a `File`. Gzip
+  ///   and zstd compressed input is decompressed with the `gzip` and `zstd`
+  ///   features.
+  ///
+  /// # Examples
+  /// ```no_run
+  /// use mongodb_schema_parser::SchemaParser;
+  /// use std::fs::File;
+  /// use std::io::BufReader;
+  ///
+  /// let mut schema_parser = SchemaParser::new();
+  /// let file = File::open("dump/zoo/cats.bson").unwrap();
+  /// schema_parser.write_bson_reader(BufReader::new(file)).unwrap();
+  /// ```
+  pub fn write_bson_reader<R: std::io::Read>(
+    &mut self,
+    reader: R,
+  ) -> Result<usize> {
+    let mut reader = BsonReader::new(decompress(reader)?);
+    let mut count = 0;
+    loop {
+      let offset = reader.offset();
+      match reader.read_block()? {
+        Some(Block::Document(doc)) => match self.write_bson(doc) {
+          Ok(()) => count += 1,
+          Err(e) => self.skip(e.at(Location::Offset(offset)), "")?,
+        },
+        Some(Block::Malformed { bytes, error }) => {
+          let e = Error::from(error).at(Location::Offset(offset));
+          self.skip(e, &hex(&bytes))?;
+        }
+        Some(Block::Terminator) => return Err(invalid_length(-1, offset)),
+        None => return Ok(count),
+      }
+    }
+  }
+
+  /// Writes every document of newline-delimited json, i.e. the output of
+  /// `mongoexport`, and returns the number of documents written. Input
+  /// starting with `[` is read with `write_json_array_reader` instead.
+  ///
+  /// Like `write_json`, documents are read as Extended JSON and empty lines
+  /// are skipped. Documents before a malformed one are kept; the error names
+  /// its line.
+  ///
+  /// # Arguments
+  /// * `reader` - Anything implementing `std::io::Read`, i.e. a `File`. Gzip
+  ///   and zstd compressed input is decompressed with the `gzip` and `zstd`
+  ///   features.
+  ///
+  /// # Examples
+  /// ```
+  /// use mongodb_schema_parser::SchemaParser;
+  ///
+  /// let mut schema_parser = SchemaParser::new();
+  /// let json = "{ \"name\": \"Chashu\" }\n{ \"name\": \"Nori\" }\n";
+  /// let count = schema_parser.write_json_reader(json.as_bytes()).unwrap();
+  /// assert_eq!(count, 2);
+  /// ```
+  pub fn write_json_reader<R: std::io::Read>(
+    &mut self,
+    reader: R,
+  ) -> Result<usize> {
+    let mut reader = decompress(reader)?;
+    if starts_with_array(&mut reader)? {
+      return self.write_json_array(reader);
+    }
+
+    let mut count = 0;
+    let mut bytes = Vec::new();
+    for i in 1.. {
+      if std::io::BufRead::read_until(&mut reader, b'\n', &mut bytes)? == 0 {
+        break;
+      }
+      // lines are read as bytes, so that one that isn't utf-8 can be skipped
+      // like any other malformed line
+      let line = match String::from_utf8(std::mem::take(&mut bytes)) {
+        Ok(line) => line,
+        Err(e) => {
+          let error = Error::from(ErrorKind::InvalidInput(e.to_string()));
+          let line = String::from_utf8_lossy(e.as_bytes());
+          self.skip(error.at(Location::Line(i)), line.trim_end())?;
+          continue;
+        }
+      };
+      if line.trim().is_empty() {
+        continue;
+      }
+      let written = Self::parse_json(&line)
+        .and_then(|doc| self.write_bson(doc))
+        .map_err(|e| e.at(Location::Line(i)));
+      match written {
+        Ok(()) => count += 1,
+        Err(e) => self.skip(e, line.trim_end())?,
+      }
+    }
+    Ok(count)
+  }
+
+  /// Writes every element of a json array of documents, i.e. the output of
+  /// `mongoexport --jsonArray`. The array is read incrementally, one document
+  /// at a time, so it never has to fit in memory. Returns the number of
+  /// documents written.
+  ///
+  /// Like `write_json`, documents are read as Extended JSON. Documents before
+  /// a malformed one are kept; the error names the line and column it was
+  /// found at.
+  ///
+  /// # Arguments
+  /// * `reader` - Anything implementing `std::io::Read`, i.e. a `File`. Gzip
+  ///   and zstd compressed input is decompressed with the `gzip` and `zstd`
+  ///   features.
+  ///
+  /// # Examples
+  /// ```
+  /// use mongodb_schema_parser::SchemaParser;
+  ///
+  /// let mut schema_parser = SchemaParser::new();
+  /// let json = r#"[{ "name": "Chashu" }, { "name": "Nori" }]"#;
+  /// let count = schema_parser.write_json_array_reader(json.as_bytes()).unwrap();
+  /// assert_eq!(count, 2);
+  /// ```
+  pub fn write_json_array_reader<R: std::io::Read>(
+    &mut self,
+    reader: R,
+  ) -> Result<usize> {
+    self.write_json_array(decompress(reader)?)
+  }
+
+  fn write_json_array<R: std::io::Read>(&mut self, reader: R) -> Result<usize> {
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    let mut error = None;
+    let count = serde::Deserializer::deserialize_seq(
+      &mut deserializer,
+      JsonArrayVisitor::new(self, &mut error),
+    );
+    if let Some(error) = error {
+      return Err(error);
+    }
+    let count = count?;
+    // only whitespace may follow the array
+    deserializer.end()?;
+    Ok(count)
+  }
+
+  /// Writes every row of a CSV or TSV file with a header line as a document,
+  /// typed the way `mongoimport` would import it, and returns the number of
+  /// documents written. Needs the `csv` feature.
+  ///
+  /// Dotted header names, i.e. `owner.name`, become nested documents. Without
+  /// `columns_have_types`, integers are `Int32` or `Long`, other numbers
+  /// `Double` and everything else `String`. With it, every header names its
+  /// type like `mongoimport --columnsHaveTypes` expects, i.e. `age.int32()`,
+  /// `indoor.boolean()` or `born.date(2006-01-02)`.
+  ///
+  /// # Arguments
+  /// * `reader` - Anything implementing `std::io::Read`, i.e. a `File`. Gzip
+  ///   and zstd compressed input is decompressed with the `gzip` and `zstd`
+  ///   features.
+  /// * `options` - Delimiter and typing, see `CsvOptions`.
+  ///
+  /// # Examples
+  /// ```
+  /// use mongodb_schema_parser::{CsvOptions, SchemaParser};
+  ///
+  /// let mut schema_parser = SchemaParser::new();
+  /// let csv = "name,age,owner.name\nChashu,4,Ada\n";
+  /// let options = CsvOptions::default();
+  /// let count = schema_parser.write_csv_reader(csv.as_bytes(), &options);
+  /// assert_eq!(count.unwrap(), 1);
+  /// ```
+  #[cfg(feature = "csv")]
+  pub fn write_csv_reader<R: std::io::Read>(
+    &mut self,
+    reader: R,
+    options: &CsvOptions,
+  ) -> Result<usize> {
+    delimited::write_csv(self, decompress(reader)?, options)
+  }
+
+  /// Reads an archive written by `mongodump --archive` and returns one
+  /// SchemaParser for every collection in it, keyed by `db.collection`.
+  /// Collections without documents are included, views and the oplog are
+  /// not.
+  ///
+  /// # Arguments
+  /// * `reader` - Anything implementing `std::io::Read`, i.e. a `File`.
+  ///   Archives written with `--gzip` are decompressed with the `gzip`
+  ///   feature.
+  ///
+  /// # Examples
+  /// ```no_run
+  /// use mongodb_schema_parser::SchemaParser;
+  /// use std::fs::File;
+  /// use std::io::BufReader;
+  ///
+  /// let file = File::open("zoo.archive").unwrap();
+  /// let schemas = SchemaParser::from_archive(BufReader::new(file)).unwrap();
+  /// for (namespace, mut schema_parser) in schemas {
+  ///   println!("{}: {:?}", namespace, schema_parser.flush());
+  /// }
+  /// ```
+  pub fn from_archive<R: std::io::Read>(
+    reader: R,
+  ) -> Result<HashMap<String, SchemaParser>> {
+    Self::from_archive_with_options(reader, &SchemaParserOptions::default())
+  }
+
+  /// Same as `from_archive`, with every collection analysed as set out in
+  /// `options`.
+  ///
+  /// # Examples
+  /// ```no_run
+  /// use mongodb_schema_parser::{SchemaParser, SchemaParserOptions};
+  /// use std::fs::File;
+  /// use std::io::BufReader;
+  ///
+  /// let file = File::open("zoo.archive").unwrap();
+  /// let options = SchemaParserOptions {
+  ///   max_values: Some(100),
+  ///   ..Default::default()
+  /// };
+  /// let schemas =
+  ///   SchemaParser::from_archive_with_options(BufReader::new(file), &options)
+  ///     .unwrap();
+  /// ```
+  pub fn from_archive_with_options<R: std::io::Read>(
+    reader: R,
+    options: &SchemaParserOptions,
+  ) -> Result<HashMap<String, SchemaParser>> {
+    archive::read_archive(decompress(reader)?, options, false)
+  }
+
+  /// Same as `from_archive_with_options`, with every SchemaParser in lenient
+  /// mode: malformed documents are skipped and recorded in the
+  /// `ingest_report` of their collection. A malformed prelude or namespace
+  /// header is still an error, since the rest of the archive can't be read
+  /// without it.
+  ///
+  /// # Examples
+  /// ```no_run
+  /// use mongodb_schema_parser::{SchemaParser, SchemaParserOptions};
+  /// use std::fs::File;
+  /// use std::io::BufReader;
+  ///
+  /// let file = File::open("zoo.archive").unwrap();
+  /// let options = SchemaParserOptions::default();
+  /// let schemas =
+  ///   SchemaParser::from_archive_lenient(BufReader::new(file), &options)
+  ///     .unwrap();
+  /// for (namespace, schema_parser) in &schemas {
+  ///   println!("{}: {}", namespace, schema_parser.ingest_report());
+  /// }
+  /// ```
+  pub fn from_archive_lenient<R: std::io::Read>(
+    reader: R,
+    options: &SchemaParserOptions,
+  ) -> Result<HashMap<String, SchemaParser>> {
+    archive::read_archive(decompress(reader)?, options, true)
   }
 
   /// Finalizes and returns SchemaParser struct -- result of all parsed
@@ -225,53 +703,337 @@ impl SchemaParser {
   /// println!("{}", schema);
   /// ```
   #[inline]
-  pub fn into_json(mut self) -> Result<String, failure::Error> {
+  pub fn into_json(mut self) -> Result<String> {
     let schema = self.flush();
     Ok(serde_json::to_string(&schema)?)
   }
 
+  /// Finalizes the schema and returns it in the format produced by the
+  /// mongodb-js `mongodb-schema` package, as consumed by Compass.
+  ///
+  /// # Examples
+  /// ```
+  /// use mongodb_schema_parser::SchemaParser;
+  ///
+  /// let mut schema_parser = SchemaParser::new();
+  /// let json = r#"{ "name": "Chashu", "type": "Cat" }"#;
+  /// schema_parser.write_json(&json).unwrap();
+  /// let schema = schema_parser.to_compass();
+  /// assert_eq!(schema.fields.len(), 2);
+  /// ```
+  pub fn to_compass(&mut self) -> CompassSchema {
+    self.finalise_schema();
+    CompassSchema::from(&*self)
+  }
+
+  /// Returns a serde_json string of the Compass-compatible schema. See
+  /// `to_compass` for details of the format.
+  ///
+  /// # Examples
+  /// ```
+  /// use mongodb_schema_parser::SchemaParser;
+  ///
+  /// let mut schema_parser = SchemaParser::new();
+  /// let json = r#"{ "name": "Chashu", "type": "Cat" }"#;
+  /// schema_parser.write_json(&json).unwrap();
+  /// let schema = schema_parser.into_compass_json().unwrap();
+  /// println!("{}", schema);
+  /// ```
+  #[inline]
+  pub fn into_compass_json(mut self) -> Result<String> {
+    let schema = self.to_compass();
+    Ok(serde_json::to_string(&schema)?)
+  }
+
+  /// Compares this schema to `other` and returns the paths that were added
+  /// or removed, the BSON types that appeared or vanished for each path, and
+  /// probability changes larger than `DEFAULT_PROBABILITY_THRESHOLD`.
+  ///
+  /// # Examples
+  /// ```
+  /// use mongodb_schema_parser::SchemaParser;
+  ///
+  /// let mut before = SchemaParser::new();
+  /// before.write_json(r#"{ "name": "Chashu", "type": "Cat" }"#).unwrap();
+  /// let mut after = SchemaParser::new();
+  /// after.write_json(r#"{ "name": "Rey", "breed": "Viszla" }"#).unwrap();
+  ///
+  /// let diff = before.diff(&after);
+  /// assert_eq!(diff.added, vec!["breed".to_string()]);
+  /// assert_eq!(diff.removed, vec!["type".to_string()]);
+  /// println!("{}", diff);
+  /// ```
+  pub fn diff(&self, other: &SchemaParser) -> SchemaDiff {
+    self.diff_with_threshold(other, DEFAULT_PROBABILITY_THRESHOLD)
+  }
+
+  /// Same as `diff`, but only reports probability changes larger than
+  /// `threshold`.
+  pub fn diff_with_threshold(
+    &self,
+    other: &SchemaParser,
+    threshold: f32,
+  ) -> SchemaDiff {
+    SchemaDiff::new(self, other, threshold)
+  }
+
+  /// Compares the values of fields present in both this schema and `other`:
+  /// shifts in numeric distributions, changes in the most frequent strings,
+  /// null rate changes and cardinality jumps. Uses the default
+  /// `DriftOptions`.
+  ///
+  /// Fields where either schema kept fewer values than it saw, because of
+  /// `max_values`, are only compared by null rate: their values are the
+  /// first ones written, not a sample.
+  ///
+  /// # Examples
+  /// ```
+  /// use mongodb_schema_parser::SchemaParser;
+  ///
+  /// let mut before = SchemaParser::new();
+  /// before.write_json(r#"{ "name": "Chashu", "type": "Cat" }"#).unwrap();
+  /// let mut after = SchemaParser::new();
+  /// after.write_json(r#"{ "name": "Rey", "type": null }"#).unwrap();
+  ///
+  /// let report = before.drift(&after);
+  /// println!("{}", report);
+  /// ```
+  pub fn drift(&self, other: &SchemaParser) -> DriftReport {
+    self.drift_with_options(other, &DriftOptions::default())
+  }
+
+  /// Same as `drift`, with custom thresholds.
+  pub fn drift_with_options(
+    &self,
+    other: &SchemaParser,
+    options: &DriftOptions,
+  ) -> DriftReport {
+    DriftReport::new(self, other, options)
+  }
+
+  /// Checks a new document against this schema and returns how it deviates:
+  /// fields that were never seen before, types that are unseen or rare for a
+  /// path, and missing fields that most documents have. Uses the default
+  /// `CheckOptions`.
+  ///
+  /// # Examples
+  /// ```
+  /// use mongodb_schema_parser::{Deviation, SchemaParser};
+  /// use bson::doc;
+  ///
+  /// let mut schema_parser = SchemaParser::new();
+  /// schema_parser.write_bson(doc! {"name": "Chashu", "type": "Cat"}).unwrap();
+  ///
+  /// let deviations = schema_parser.check_document(&doc! {"name": "Rey"});
+  /// assert_eq!(
+  ///   deviations,
+  ///   vec![Deviation::MissingField { path: "type".to_string(), probability: 1.0 }]
+  /// );
+  /// ```
+  pub fn check_document(&self, doc: &Document) -> Vec<Deviation> {
+    self.check_document_with_options(doc, &CheckOptions::default())
+  }
+
+  /// Same as `check_document`, with custom thresholds.
+  pub fn check_document_with_options(
+    &self,
+    doc: &Document,
+    options: &CheckOptions,
+  ) -> Vec<Deviation> {
+    let mut deviations = Vec::new();
+    check::check_document(self, doc, None, options, &mut deviations);
+    deviations
+  }
+
+  /// Returns the types that make up less than `DEFAULT_ANOMALY_THRESHOLD` of
+  /// a field with more than one type, i.e. a few String values in a field
+  /// that is otherwise Int32. Each anomaly lists example values and, for
+  /// documents with an `_id`, the `_id`s of the first few documents it was
+  /// found in.
+  ///
+  /// # Examples
+  /// ```
+  /// use mongodb_schema_parser::SchemaParser;
+  /// use bson::doc;
+  ///
+  /// let mut schema_parser = SchemaParser::new();
+  /// for i in 0..200 {
+  ///   schema_parser.write_bson(doc! {"_id": i, "age": i}).unwrap();
+  /// }
+  /// schema_parser.write_bson(doc! {"_id": 200, "age": "two"}).unwrap();
+  ///
+  /// let report = schema_parser.anomalies();
+  /// assert_eq!(report.anomalies[0].bson_type, "String");
+  /// println!("{}", report);
+  /// ```
+  pub fn anomalies(&self) -> AnomalyReport {
+    self.anomalies_with_threshold(DEFAULT_ANOMALY_THRESHOLD)
+  }
+
+  /// Same as `anomalies`, reporting types below `threshold` instead.
+  pub fn anomalies_with_threshold(&self, threshold: f32) -> AnomalyReport {
+    AnomalyReport::new(self, threshold)
+  }
+
+  /// Lists fields that likely hold personal data, i.e. for a compliance
+  /// inventory of a collection. String and binary values are checked for
+  /// emails, phone numbers, credit card numbers (passing the Luhn check),
+  /// national IDs and IP addresses as they are written, so every value is
+  /// counted, including those not kept or redacted. Fields with a name
+  /// listed in `SENSITIVE_NAMES` are reported whatever their values are.
+  ///
+  /// ```
+  /// use mongodb_schema_parser::{PiiKind, SchemaParser};
+  /// use bson::doc;
+  ///
+  /// let mut schema_parser = SchemaParser::new();
+  /// schema_parser
+  ///   .write_bson(doc! {"name": "Nori", "contact": "irina@example.com"})
+  ///   .unwrap();
+  ///
+  /// let report = schema_parser.pii();
+  /// assert_eq!(report.fields[0].path, "contact");
+  /// assert_eq!(report.fields[0].kinds[0].kind, PiiKind::Email);
+  /// println!("{}", report);
+  /// ```
+  pub fn pii(&self) -> PiiReport {
+    PiiReport::new(self)
+  }
+
+  /// Suggests how to encrypt the fields `pii` reports, with client-side field
+  /// level encryption or Queryable Encryption. Fields whose values are
+  /// mostly distinct, of a type that can be queried by equality, look like
+  /// identifiers that are queried, and are encrypted deterministically; the
+  /// others randomly. Fields nested in arrays are encrypted with the whole
+  /// array. Fields holding Binary subtype 6 are listed as encrypted
+  /// already. Call `flush` first.
+  ///
+  /// ```
+  /// use mongodb_schema_parser::{EncryptionAlgorithm, SchemaParser};
+  /// use bson::doc;
+  ///
+  /// let mut schema_parser = SchemaParser::new();
+  /// schema_parser
+  ///   .write_bson(doc! {"name": "Nori", "email": "irina@example.com"})
+  ///   .unwrap();
+  /// schema_parser.flush();
+  ///
+  /// let report = schema_parser.suggest_encryption();
+  /// let email = &report.suggestions[0];
+  /// assert_eq!(email.algorithm, EncryptionAlgorithm::Deterministic);
+  /// println!("{}", report.schema_map());
+  /// ```
+  pub fn suggest_encryption(&self) -> EncryptionReport {
+    self.suggest_encryption_with_threshold(DEFAULT_CARDINALITY_THRESHOLD)
+  }
+
+  /// Same as `suggest_encryption`, encrypting fields deterministically when
+  /// at least `threshold` of their values are distinct instead.
+  pub fn suggest_encryption_with_threshold(
+    &self,
+    threshold: f32,
+  ) -> EncryptionReport {
+    EncryptionReport::new(self, threshold)
+  }
+
+  #[inline]
+  fn parse_json(json: &str) -> Result<Document> {
+    let val: Value = serde_json::from_str(json)?;
+    Self::parse_value(val)
+  }
+
+  #[inline]
+  fn parse_value(val: Value) -> Result<Document> {
+    // parses canonical and relaxed extended json, i.e. `{"$oid": ..}` into an
+    // ObjectId rather than a document with a `$oid` field.
+    match Bson::try_from(val)? {
+      Bson::Document(doc) => Ok(doc),
+      bson => Err(ErrorKind::NotADocument(bson.element_type()).into()),
+    }
+  }
+
+  #[inline]
+  fn write_document(&mut self, doc: Document) -> Result<()> {
+    // the context borrows the options while the fields are written
+    let options = std::mem::take(&mut self.options);
+    let checked = limits::check(&doc, &options);
+    if checked.is_ok() {
+      self.update_count();
+      let context = WriteContext::new(&doc, &options);
+      self.generate_field(&doc, None, None, &context);
+    }
+    self.options = options;
+    checked
+  }
+
   #[inline]
   fn generate_field(
     &mut self,
-    doc: Document,
+    doc: &Document,
     path: Option<String>,
     count: Option<usize>,
+    context: &WriteContext,
   ) {
     if let Some(_count) = count {
       self.update_count();
     }
-    for (key, value) in doc {
+    let options = context.options;
+    let max_fields = options.max_fields.unwrap_or(usize::MAX);
+    // only the top level document is measured
+    let max_bytes = match options.max_document_bytes {
+      Some(max) if context.depth == 1 => max,
+      _ => usize::MAX,
+    };
+    // length and trailing nul byte
+    let mut bytes = 5;
+    for (i, (key, value)) in doc.iter().enumerate() {
+      if max_bytes != usize::MAX {
+        bytes += limits::element_size(key, value);
+      }
+      if i >= max_fields || bytes > max_bytes {
+        *self.truncated_documents.get_or_insert(0) += 1;
+        break;
+      }
       let current_path = Field::get_path(key.to_owned(), path.to_owned());
-      self.update_or_create_field(key.to_owned(), &value, &current_path)
+      if !projection::is_selected(context.options, &current_path, value) {
+        continue;
+      }
+      self.update_or_create_field(key.to_owned(), value, &current_path, context)
     }
   }
 
   #[inline]
-  fn update_or_create_field(&mut self, key: String, value: &Bson, path: &str) {
+  fn update_or_create_field(
+    &mut self,
+    key: String,
+    value: &Bson,
+    path: &str,
+    context: &WriteContext,
+  ) {
     // check if we already have a field for this key;
     // if name exist, call self.update_field, otherwise create new
     if self.fields.contains_key(&key) {
-      self.update_field(&key, value);
+      self.update_field(&key, value, context);
     } else {
       let mut field = Field::new(key, path);
-      field.create_type(value);
+      field.create_type(value, context);
       self.fields.insert(field.name.to_string(), field);
     }
   }
 
   #[inline]
-  fn update_field(&mut self, key: &str, value: &Bson) {
+  fn update_field(&mut self, key: &str, value: &Bson, context: &WriteContext) {
     let field = self.fields.get_mut(key);
     if let Some(field) = field {
       field.update_count();
-      if !field.does_field_type_exist(&value) {
+      if !field.does_field_type_exist(value) {
         // field type doesn't exist in field.types, create a new field_type
-        field.create_type(&value);
+        field.create_type(value, context);
       } else {
-        let type_val = FieldType::get_type(&value);
+        let type_val = FieldType::get_type(value);
         let field_type = field.types.get_mut(&type_val);
         if let Some(field_type) = field_type {
-          field_type.update_type(&value);
+          field_type.update_type(value, context);
         }
       }
     }
@@ -279,6 +1041,13 @@ impl SchemaParser {
 
   #[inline]
   pub fn finalise_schema(&mut self) {
+    let options = std::mem::take(&mut self.options);
+    self.finalise_with(&options);
+    self.options = options;
+  }
+
+  // Nested schemas are finalised with the options of the top level one.
+  pub(crate) fn finalise_with(&mut self, options: &SchemaParserOptions) {
     for field in self.fields.values_mut() {
       // If bson_types includes a Document, find that document and let its schema
       // field update its own missing fields.
@@ -286,7 +1055,7 @@ impl SchemaParser {
       if let Some(field_type) = field_type {
         let schema = &mut field_type.schema;
         if let Some(schema) = schema {
-          return schema.finalise_schema();
+          schema.finalise_with(options);
         }
       }
 
@@ -297,7 +1066,7 @@ impl SchemaParser {
       }
 
       // check for duplicates, unique values, set probability
-      field.finalise_field(self.count);
+      field.finalise_field(self.count, options);
     }
   }
 
@@ -305,6 +1074,33 @@ impl SchemaParser {
   fn update_count(&mut self) {
     self.count += 1
   }
+
+  // Calls `f` with every field of this schema and the document count of the
+  // schema it belongs to, descending into subdocuments and documents inside
+  // arrays.
+  pub(crate) fn walk_fields<'a, F>(&'a self, f: &mut F)
+  where
+    F: FnMut(&'a Field, usize),
+  {
+    for field in self.fields.values() {
+      f(field, self.count);
+      for field_type in field.types.values() {
+        for schema in field_type.schemas() {
+          schema.walk_fields(f);
+        }
+      }
+    }
+  }
+}
+
+// Share of `count` in `total`, or zero when there is nothing to share.
+#[inline]
+pub(crate) fn ratio(count: usize, total: usize) -> f32 {
+  if total == 0 {
+    0.0
+  } else {
+    count as f32 / total as f32
+  }
 }
 
 #[cfg(test)]
@@ -328,11 +1124,25 @@ mod tests {
   fn it_writes_json() {
     let mut schema_parser = SchemaParser::new();
     let json_str = r#"{"name": "Nori", "type": "Cat"}"#;
-    schema_parser.write_json(&json_str).unwrap();
+    schema_parser.write_json(json_str).unwrap();
     assert_eq!(schema_parser.count, 1);
     assert_eq!(schema_parser.fields.len(), 2);
   }
 
+  #[test]
+  fn it_writes_bson_reader() {
+    let mut schema_parser = SchemaParser::new();
+    let mut bytes = Vec::new();
+    doc! {"name": "Nori"}.to_writer(&mut bytes).unwrap();
+    doc! {"name": "Rey", "type": "Dog"}
+      .to_writer(&mut bytes)
+      .unwrap();
+    let count = schema_parser.write_bson_reader(bytes.as_slice()).unwrap();
+    assert_eq!(count, 2);
+    assert_eq!(schema_parser.count, 2);
+    assert_eq!(schema_parser.fields.len(), 2);
+  }
+
   // #[bench]
   // fn bench_it_writes_json(bench: &mut Bencher) {
   //   let mut schema_parser = SchemaParser::new();
@@ -358,9 +1168,427 @@ mod tests {
       "type": "Cat"
     };
     schema_parser.write_bson(bson_doc).unwrap();
-    println!("{:?}", schema_parser.flush());
+    let output = schema_parser.flush();
+    assert_eq!(output.count, 1);
+    assert_eq!(output.fields.len(), 2);
+    assert_eq!(output.fields["name"].bson_types, vec!["String"]);
+    assert_eq!(output.fields["type"].count, 1);
+  }
+
+  #[test]
+  fn it_writes_json_array_reader() {
+    let mut schema_parser = SchemaParser::new();
+    let json = r#"[
+      {"name": "Nori", "born": {"$date": "2019-09-03T09:24:58.000Z"}},
+      {"name": "Rey", "type": "Dog"}
+    ]"#;
+    let count = schema_parser
+      .write_json_array_reader(json.as_bytes())
+      .unwrap();
+    assert_eq!(count, 2);
+    assert_eq!(schema_parser.count, 2);
+    assert!(schema_parser.fields["born"]
+      .types
+      .contains_key("UtcDatetime"));
+  }
+
+  #[test]
+  fn it_keeps_json_array_elements_before_an_error() {
+    let mut schema_parser = SchemaParser::new();
+    let json = "[{\"name\": \"Nori\"},\n 12]";
+    let error = schema_parser
+      .write_json_array_reader(json.as_bytes())
+      .unwrap_err();
     assert_eq!(schema_parser.count, 1);
-    assert_eq!(schema_parser.fields.len(), 2);
+    assert_eq!(error.location(), Some(Location::Element(1)));
+    assert_eq!(
+      error.to_string(),
+      "array element 1: expected a document, found Int32"
+    );
+  }
+
+  #[test]
+  fn it_rejects_json_that_is_not_an_array() {
+    let mut schema_parser = SchemaParser::new();
+    let json = r#"{"name": "Nori"}"#;
+    assert!(schema_parser
+      .write_json_array_reader(json.as_bytes())
+      .is_err());
+    let json = r#"[{"name": "Nori"}] {"name": "Rey"}"#;
+    assert!(schema_parser
+      .write_json_array_reader(json.as_bytes())
+      .is_err());
+  }
+
+  #[test]
+  fn it_skips_malformed_json_lines() {
+    let mut schema_parser = SchemaParser::new();
+    schema_parser.set_lenient(true);
+    let json = "{\"name\": \"Nori\"}\n[1]\n{\"_id\": {\"$oid\": 1}}\n{\"n";
+    let count = schema_parser.write_json_reader(json.as_bytes()).unwrap();
+    assert_eq!(count, 1);
+    assert_eq!(schema_parser.count, 1);
+    let report = schema_parser.ingest_report();
+    assert_eq!(report.skipped, 3);
+    let kinds: Vec<(Location, IngestErrorKind)> = report
+      .errors
+      .iter()
+      .map(|error| (error.location, error.kind))
+      .collect();
+    assert_eq!(
+      kinds,
+      vec![
+        (Location::Line(2), IngestErrorKind::NotADocument),
+        (Location::Line(3), IngestErrorKind::InvalidValue),
+        (Location::Line(4), IngestErrorKind::Syntax),
+      ]
+    );
+    assert_eq!(report.errors[0].snippet, "[1]");
+    assert!(report.errors[2].message.starts_with("line 4: "));
+  }
+
+  #[test]
+  fn it_skips_json_lines_that_are_not_utf8() {
+    let mut schema_parser = SchemaParser::new();
+    schema_parser.set_lenient(true);
+    let json: &[u8] =
+      b"{\"name\": \"Nori\"}\n{\"name\": \"R\xffy\"}\n{\"name\": \"Rey\"}\n";
+    let count = schema_parser.write_json_reader(json).unwrap();
+    assert_eq!(count, 2);
+    let report = schema_parser.ingest_report();
+    assert_eq!(report.skipped, 1);
+    assert_eq!(report.errors[0].location, Location::Line(2));
+    assert_eq!(report.errors[0].snippet, "{\"name\": \"R\u{fffd}y\"}");
+    // without lenient mode the line is still an error
+    let mut schema_parser = SchemaParser::new();
+    assert!(schema_parser.write_json_reader(json).is_err());
+  }
+
+  #[test]
+  fn it_skips_malformed_json_array_elements() {
+    let mut schema_parser = SchemaParser::new();
+    schema_parser.set_lenient(true);
+    let json = r#"[{"name": "Nori"}, 2, {"name": "Rey"}]"#;
+    let count = schema_parser
+      .write_json_array_reader(json.as_bytes())
+      .unwrap();
+    assert_eq!(count, 2);
+    let error = &schema_parser.ingest_report().errors[0];
+    assert_eq!(error.location, Location::Element(1));
+    assert_eq!(error.snippet, "2");
+    // the rest of a malformed array can't be read
+    let json = r#"[{"name": "Nori"}, {"name" 2}]"#;
+    assert!(schema_parser
+      .write_json_array_reader(json.as_bytes())
+      .is_err());
+  }
+
+  #[test]
+  fn it_skips_malformed_bson_documents() {
+    let mut bytes = Vec::new();
+    doc! {"name": "Nori"}.to_writer(&mut bytes).unwrap();
+    let offset = bytes.len() as u64;
+    // a valid length, but an unknown element type
+    bytes.extend_from_slice(&[0x08, 0x00, 0x00, 0x00, 0x42, 0x61, 0x00, 0x00]);
+    doc! {"name": "Rey"}.to_writer(&mut bytes).unwrap();
+
+    let mut schema_parser = SchemaParser::new();
+    assert!(schema_parser.write_bson_reader(bytes.as_slice()).is_err());
+
+    let mut schema_parser = SchemaParser::new();
+    schema_parser.set_lenient(true);
+    let count = schema_parser.write_bson_reader(bytes.as_slice()).unwrap();
+    assert_eq!(count, 2);
+    let error = &schema_parser.ingest_report().errors[0];
+    assert_eq!(error.location, Location::Offset(offset));
+    assert_eq!(error.kind, IngestErrorKind::InvalidBson);
+    assert_eq!(error.snippet, "0800000042610000");
+  }
+
+  #[test]
+  fn it_returns_typed_errors() {
+    let mut schema_parser = SchemaParser::new();
+    schema_parser.write_json(r#"{"name": "Nori"}"#).unwrap();
+    let error = schema_parser.write_json("[1]").unwrap_err();
+    assert!(matches!(
+      error.kind(),
+      ErrorKind::NotADocument(bson::spec::ElementType::Array)
+    ));
+    assert_eq!(error.location(), Some(Location::Document(1)));
+    assert_eq!(
+      error.to_string(),
+      "document 1: expected a document, found Array"
+    );
+    let error = schema_parser
+      .write_json(r#"{"a": {"$oid": 1}}"#)
+      .unwrap_err();
+    assert!(matches!(error.kind(), ErrorKind::ExtendedJson(_)));
+    let error = schema_parser.write_json("{").unwrap_err();
+    assert!(matches!(error.kind(), ErrorKind::Json(_)));
+  }
+
+  #[test]
+  fn it_limits_values() {
+    let options = SchemaParserOptions {
+      max_values: Some(2),
+      ..Default::default()
+    };
+    let mut schema_parser = SchemaParser::with_options(options).unwrap();
+    for name in &["Nori", "Rey", "Nori", "Chashu"] {
+      schema_parser.write_bson(doc! {"name": name}).unwrap();
+    }
+    let schema = schema_parser.flush();
+    let string = &schema.fields["name"].types["String"];
+    assert_eq!(string.count, 4);
+    assert_eq!(string.values.len(), 2);
+    assert_eq!(string.unique, Some(2));
+
+    let options = SchemaParserOptions {
+      unique: false,
+      ..Default::default()
+    };
+    let mut schema_parser = SchemaParser::with_options(options).unwrap();
+    schema_parser.write_bson(doc! {"name": "Nori"}).unwrap();
+    let schema = schema_parser.flush();
+    assert_eq!(schema.fields["name"].types["String"].unique, None);
+  }
+
+  #[test]
+  fn it_limits_depth() {
+    let options = SchemaParserOptions {
+      max_depth: Some(2),
+      ..Default::default()
+    };
+    let mut schema_parser = SchemaParser::with_options(options).unwrap();
+    let doc = doc! {
+      "owner": {"name": "Irina", "address": {"city": "Berlin"}},
+      "toys": [["ball"], {"name": "mouse"}],
+    };
+    schema_parser.write_bson(doc).unwrap();
+    let schema = schema_parser.flush();
+
+    let owner = schema.fields["owner"].types["Document"].schema.as_ref();
+    let owner = owner.unwrap();
+    assert!(owner.fields.contains_key("name"));
+    let address = &owner.fields["address"].types["Document"];
+    assert_eq!(address.count, 1);
+    assert!(address.schema.is_none());
+
+    let toys = &schema.fields["toys"].types["Array"];
+    assert!(toys.types["Array"].types.is_empty());
+    assert_eq!(toys.types["Array"].truncated, Some(1));
+    assert!(toys.types["Document"].schema.is_none());
+  }
+
+  #[test]
+  fn it_limits_depth_by_default() {
+    let mut doc = doc! {};
+    for _ in 0..1000 {
+      doc = doc! {"a": doc};
+    }
+    let mut schema_parser = SchemaParser::new();
+    schema_parser.write_bson(doc).unwrap();
+    let mut schema = &schema_parser.flush();
+    for _ in 1..100 {
+      let document = &schema.fields["a"].types["Document"];
+      schema = document.schema.as_ref().unwrap();
+    }
+    let document = &schema.fields["a"].types["Document"];
+    assert!(document.schema.is_none());
+    assert_eq!(document.truncated, Some(1));
+  }
+
+  #[test]
+  fn it_truncates_at_limits() {
+    let options = SchemaParserOptions {
+      max_fields: Some(2),
+      max_array_elements: Some(2),
+      ..Default::default()
+    };
+    let mut schema_parser = SchemaParser::with_options(options).unwrap();
+    let doc = doc! {
+      "name": "Chashu",
+      "toys": ["ball", "mouse", 3],
+      "owner": "Irina",
+    };
+    schema_parser.write_bson(doc).unwrap();
+    schema_parser.write_bson(doc! {"name": "Nori"}).unwrap();
+    let schema = schema_parser.flush();
+    assert_eq!(schema.count, 2);
+    assert_eq!(schema.truncated_documents, Some(1));
+    assert!(!schema.fields.contains_key("owner"));
+    let toys = &schema.fields["toys"].types["Array"];
+    assert_eq!(toys.truncated, Some(1));
+    assert!(!toys.types.contains_key("Int32"));
+
+    let options = SchemaParserOptions {
+      max_document_bytes: Some(32),
+      ..Default::default()
+    };
+    let mut schema_parser = SchemaParser::with_options(options).unwrap();
+    // 5 bytes of length and nul byte, 14 for "name" and 17 for "type"
+    let doc = doc! {"name": "Rey", "type": "Viszla"};
+    schema_parser.write_bson(doc).unwrap();
+    let schema = schema_parser.flush();
+    assert_eq!(schema.fields.keys().collect::<Vec<_>>(), vec!["name"]);
+    assert_eq!(schema.truncated_documents, Some(1));
+  }
+
+  #[test]
+  fn it_rejects_documents_past_limits() {
+    let options = SchemaParserOptions {
+      max_depth: Some(2),
+      on_limit: OnLimit::Error,
+      ..Default::default()
+    };
+    let mut schema_parser =
+      SchemaParser::with_options(options.clone()).unwrap();
+    schema_parser.write_json(r#"{"name": "Nori"}"#).unwrap();
+    let json = r#"{"owner": {"address": {"city": "Berlin"}}}"#;
+    let error = schema_parser.write_json(json).unwrap_err();
+    assert_eq!(
+      error.to_string(),
+      "document 1, field 'owner.address': exceeds the max_depth limit of 2"
+    );
+    // nothing of the document was written
+    assert_eq!(schema_parser.count, 1);
+    assert!(!schema_parser.fields.contains_key("owner"));
+
+    let mut schema_parser = SchemaParser::with_options(options).unwrap();
+    schema_parser.set_lenient(true);
+    let json = format!("{}\n{}\n", json, r#"{"name": "Nori"}"#);
+    let count = schema_parser.write_json_reader(json.as_bytes()).unwrap();
+    assert_eq!(count, 1);
+    let error = &schema_parser.ingest_report().errors[0];
+    assert_eq!(error.location, Location::Line(1));
+    assert_eq!(error.kind, IngestErrorKind::LimitExceeded);
+  }
+
+  #[test]
+  fn it_skips_arrays() {
+    let options = SchemaParserOptions {
+      analyse_arrays: false,
+      ..Default::default()
+    };
+    let mut schema_parser = SchemaParser::with_options(options).unwrap();
+    schema_parser
+      .write_bson(doc! {"toys": ["ball", 1]})
+      .unwrap();
+    let schema = schema_parser.flush();
+    let toys = &schema.fields["toys"].types["Array"];
+    assert_eq!(toys.count, 1);
+    assert!(toys.types.is_empty());
+    assert!(toys.values.is_empty());
+    assert!(toys.lengths.is_empty());
+  }
+
+  #[test]
+  fn it_projects_fields() {
+    let options = SchemaParserOptions {
+      include: vec!["owner.*".to_string(), "toys.name".to_string()],
+      exclude: vec!["owner.address".to_string()],
+      ..Default::default()
+    };
+    let mut schema_parser = SchemaParser::with_options(options).unwrap();
+    let doc = doc! {
+      "name": "Chashu",
+      "owner": {"name": "Irina", "address": {"city": "Berlin"}},
+      "toys": [{"name": "mouse", "price": 2}],
+    };
+    schema_parser.write_bson(doc).unwrap();
+    let schema = schema_parser.flush();
+
+    let mut fields: Vec<&String> = schema.fields.keys().collect();
+    fields.sort();
+    assert_eq!(fields, vec!["owner", "toys"]);
+    let owner = schema.fields["owner"].types["Document"].schema.as_ref();
+    let owner = owner.unwrap();
+    assert_eq!(owner.fields.keys().collect::<Vec<_>>(), vec!["name"]);
+    let toys = &schema.fields["toys"].types["Array"].types["Document"];
+    let toys = toys.schema.as_ref().unwrap();
+    assert_eq!(toys.fields.keys().collect::<Vec<_>>(), vec!["name"]);
+  }
+
+  #[test]
+  fn it_redacts_values() {
+    let docs = vec![
+      doc! {"name": "Nori", "owner": {"email": "irina@example.com"}},
+      doc! {"name": "Rey", "owner": {"email": "irina@example.com"}},
+      doc! {"name": "Chashu", "owner": {"email": "lrlna@example.com"}},
+    ];
+    let options = SchemaParserOptions {
+      redact_sensitive: true,
+      ..Default::default()
+    };
+    let mut schema_parser =
+      SchemaParser::with_options(options.clone()).unwrap();
+    for doc in docs.clone() {
+      schema_parser.write_bson(doc).unwrap();
+    }
+    let schema = schema_parser.flush();
+    let owner = &schema.fields["owner"].types["Document"];
+    let email = &owner.schema.as_ref().unwrap().fields["email"];
+    let email = &email.types["String"];
+    assert_eq!(email.count, 3);
+    assert!(email.values.is_empty());
+    assert_eq!(email.unique, None);
+    assert_eq!(schema.fields["name"].types["String"].values.len(), 3);
+
+    let options = SchemaParserOptions {
+      redaction: Redaction::Hash,
+      salt: "pepper".to_string(),
+      ..options
+    };
+    let mut schema_parser = SchemaParser::with_options(options).unwrap();
+    for doc in docs {
+      schema_parser.write_bson(doc).unwrap();
+    }
+    let json = schema_parser.into_json().unwrap();
+    assert!(!json.contains("example.com"));
+    assert!(json.contains("Chashu"));
+  }
+
+  #[test]
+  fn it_needs_a_salt_to_hash() {
+    let options = SchemaParserOptions {
+      redact_sensitive: true,
+      redaction: Redaction::Hash,
+      ..Default::default()
+    };
+    let error = SchemaParser::with_options(options.clone()).unwrap_err();
+    assert!(matches!(error.kind(), ErrorKind::InvalidOptions(_)));
+    assert_eq!(error.to_string(), "Redaction::Hash needs a salt");
+    let archive = SchemaParser::from_archive_with_options(&b""[..], &options);
+    let error = archive.unwrap_err();
+    assert!(matches!(error.kind(), ErrorKind::InvalidOptions(_)));
+  }
+
+  #[test]
+  fn it_reads_partial_options() {
+    let options: SchemaParserOptions =
+      serde_json::from_str(r#"{"max_depth": 2}"#).unwrap();
+    assert_eq!(
+      options,
+      SchemaParserOptions {
+        max_depth: Some(2),
+        ..Default::default()
+      }
+    );
+  }
+
+  #[test]
+  fn it_skips_documents_passed_one_by_one() {
+    let mut schema_parser = SchemaParser::new();
+    schema_parser.set_lenient(true);
+    schema_parser.write_json(r#"{"name": "Nori"}"#).unwrap();
+    schema_parser.write_json(r#"{"name": "#).unwrap();
+    schema_parser.write_json(r#"{"name": "Rey"}"#).unwrap();
+    assert_eq!(schema_parser.count, 2);
+    let report = schema_parser.ingest_report();
+    assert_eq!(report.errors[0].location, Location::Document(1));
+    schema_parser.set_lenient(false);
+    assert!(schema_parser.write_json(r#"{"name": "#).is_err());
+    assert_eq!(schema_parser.ingest_report().skipped, 1);
   }
 
   // #[bench]
@@ -374,7 +1602,7 @@ mod tests {
   fn it_flushes() {
     let mut schema_parser = SchemaParser::new();
     let json_str = r#"{"name": "Nori", "type": "Cat"}"#;
-    schema_parser.write_json(&json_str).unwrap();
+    schema_parser.write_json(json_str).unwrap();
     let output = schema_parser.flush();
     assert_eq!(output.count, 1);
     assert_eq!(output.fields.len(), 2);
@@ -386,9 +1614,9 @@ mod tests {
     let json_str1 = r#"{"name": "Nori", "type": "Cat"}"#;
     let json_str2 = r#"{"name": "Rey"}"#;
     let json_str3 = r#"{"name": "Chashu"}"#;
-    schema_parser.write_json(&json_str1).unwrap();
-    schema_parser.write_json(&json_str2).unwrap();
-    schema_parser.write_json(&json_str3).unwrap();
+    schema_parser.write_json(json_str1).unwrap();
+    schema_parser.write_json(json_str2).unwrap();
+    schema_parser.write_json(json_str3).unwrap();
     let mut output = schema_parser.flush();
     let type_field = output.fields.get_mut("type");
     if let Some(type_field) = type_field {
@@ -402,13 +1630,49 @@ mod tests {
     }
   }
 
+  #[test]
+  fn it_adds_missing_to_explicit_nulls() {
+    let mut schema_parser = SchemaParser::new();
+    schema_parser
+      .write_json(r#"{"name": "Nori", "type": null}"#)
+      .unwrap();
+    schema_parser
+      .write_json(r#"{"name": "Rey", "type": "Dog"}"#)
+      .unwrap();
+    schema_parser.write_json(r#"{"name": "Chashu"}"#).unwrap();
+    let output = schema_parser.flush();
+    let type_field = &output.fields["type"];
+    assert_eq!(type_field.count, 3);
+    assert_eq!(type_field.missing, 1);
+    assert_eq!(type_field.present_count(), 2);
+    // the explicit null and the missing document share one Null type
+    let nulls = type_field.bson_types.iter().filter(|t| *t == "Null");
+    assert_eq!(nulls.count(), 1);
+    let null_field_type = &type_field.types["Null"];
+    assert_eq!(null_field_type.count, 2);
+    assert_eq!(type_field.type_count(null_field_type), 1);
+  }
+
+  #[test]
+  fn it_counts_every_element_of_an_element_type() {
+    let mut schema_parser = SchemaParser::new();
+    schema_parser
+      .write_json(r#"{"tags": ["cat", "small", "grey"]}"#)
+      .unwrap();
+    schema_parser.write_json(r#"{"tags": ["dog", 1]}"#).unwrap();
+    let output = schema_parser.flush();
+    let array_type = &output.fields["tags"].types["Array"];
+    assert_eq!(array_type.types["String"].count, 4);
+    assert_eq!(array_type.types["Int32"].count, 1);
+  }
+
   #[test]
   fn it_adjusts_missing_with_nested_document() {
     let mut schema_parser = SchemaParser::new();
     let json_str1 = r#"{"name": "Nori", "type": {"breed": "Norwegian Forest", "type": "cat"}}"#;
     let json_str2 = r#"{"name": "Rey", "type": {"breed": "Viszla"}}"#;
-    schema_parser.write_json(&json_str1).unwrap();
-    schema_parser.write_json(&json_str2).unwrap();
+    schema_parser.write_json(json_str1).unwrap();
+    schema_parser.write_json(json_str2).unwrap();
     let output = schema_parser.flush();
     let type_field = output.fields.get("type");
     if let Some(type_field) = type_field {
@@ -440,7 +1704,7 @@ mod tests {
     let mut schema_parser = SchemaParser::new();
     assert_eq!(schema_parser.count, 0);
     let json_str = r#"{"name": "Chashu", "type": "Cat"}"#;
-    schema_parser.write_json(&json_str).unwrap();
+    schema_parser.write_json(json_str).unwrap();
     assert_eq!(schema_parser.count, 1);
   }
 
@@ -448,9 +1712,9 @@ mod tests {
   fn it_updates_fields() {
     let mut schema_parser = SchemaParser::new();
     let json_str = r#"{"name": "Chashu", "type": "Cat"}"#;
-    schema_parser.write_json(&json_str).unwrap();
+    schema_parser.write_json(json_str).unwrap();
     let name = Bson::String("Nori".to_owned());
-    schema_parser.update_field("name", &name);
+    schema_parser.update_field("name", &name, &WriteContext::default());
     let vec = vec![
       ValueType::Str("Chashu".to_owned()),
       ValueType::Str("Nori".to_owned()),
@@ -481,7 +1745,7 @@ mod tests {
       "name": "Rey",
       "type": "Dog"
     };
-    schema_parser.generate_field(doc, None, None);
+    schema_parser.generate_field(&doc, None, None, &WriteContext::default());
     assert_eq!(schema_parser.fields.len(), 2);
     if let Some(f) = schema_parser.fields.get("name") {
       if let Some(t) = f.types.get("String") {
@@ -541,6 +1805,151 @@ mod tests {
     }
   }
 
+  #[test]
+  fn it_computes_array_lengths() {
+    let mut schema_parser = SchemaParser::new();
+    let toys = vec![
+      bson!([]),
+      bson!(["ball"]),
+      bson!([1, 2, 3, 4, 5, 6, 7, 8]),
+      bson!([]),
+    ];
+    for toys in toys {
+      schema_parser.write_bson(doc! {"toys": toys}).unwrap();
+    }
+    let schema = schema_parser.flush();
+    let toys = &schema.fields["toys"].types["Array"];
+    // every array is counted once, empty ones included
+    assert_eq!(toys.lengths, vec![0, 1, 8, 0]);
+    let stats = toys.length_stats.as_ref().unwrap();
+    assert_eq!((stats.min, stats.max, stats.empty), (0, 8, 2));
+    assert_eq!(stats.mean, 2.25);
+    let histogram: Vec<(usize, usize, usize)> = stats
+      .histogram
+      .iter()
+      .map(|bucket| (bucket.min, bucket.max, bucket.count))
+      .collect();
+    assert_eq!(histogram, vec![(0, 0, 2), (1, 1, 1), (8, 15, 1)]);
+  }
+
+  #[allow(clippy::float_cmp)]
+  #[test]
+  fn it_keeps_every_level_of_nested_arrays() {
+    let mut schema_parser = SchemaParser::new();
+    schema_parser
+      .write_json(r#"{"nested": [[1, 2], [3, 4, 5]], "flat": [1, 2, 3, 4]}"#)
+      .unwrap();
+    schema_parser
+      .write_json(r#"{"nested": [6, [7]], "flat": [5]}"#)
+      .unwrap();
+    let schema = schema_parser.flush();
+
+    let flat = &schema.fields["flat"].types["Array"];
+    assert_eq!(flat.lengths, vec![4, 1]);
+    assert_eq!(flat.values.len(), 5);
+    assert_eq!(flat.types["Int32"].probability, 1.0);
+
+    let nested = &schema.fields["nested"].types["Array"];
+    assert_eq!(nested.lengths, vec![2, 2]);
+    // only the number found in the outer arrays
+    assert_eq!(nested.values, vec![ValueType::I32(6)]);
+    assert_eq!(nested.types["Int32"].count, 1);
+    assert_eq!(nested.types["Int32"].probability, 0.25);
+    let inner = &nested.types["Array"];
+    assert_eq!(inner.count, 3);
+    assert_eq!(inner.probability, 0.75);
+    assert_eq!(inner.lengths, vec![2, 3, 1]);
+    assert_eq!(inner.values.len(), 6);
+    assert_eq!(inner.types["Int32"].probability, 1.0);
+  }
+
+  #[test]
+  fn it_finds_tuples() {
+    let mut schema_parser = SchemaParser::new();
+    for json in &[
+      r#"{"location": [13.4, 52.5], "pair": ["Nori", 3], "tags": ["a", "b"]}"#,
+      r#"{"location": [-73, 40.7], "pair": ["Rey", 5], "tags": ["c"]}"#,
+      r#"{"once": [1, 2]}"#,
+    ] {
+      schema_parser.write_json(json).unwrap();
+    }
+    let schema = schema_parser.flush();
+    let tuple = |name: &str| {
+      schema.fields[name].types["Array"]
+        .tuple
+        .as_ref()
+        .map(|positions| {
+          positions
+            .iter()
+            .map(|position| position.bson_types.join("|"))
+            .collect::<Vec<String>>()
+        })
+    };
+    assert_eq!(
+      tuple("location"),
+      Some(vec!["Double|Int32".to_string(), "Double".to_string()])
+    );
+    assert_eq!(
+      tuple("pair"),
+      Some(vec!["String".to_string(), "Int32".to_string()])
+    );
+    // arrays of different lengths are lists
+    assert_eq!(tuple("tags"), None);
+    assert_eq!(tuple("once"), None);
+  }
+
+  #[allow(clippy::float_cmp)]
+  #[test]
+  fn it_has_element_probabilities_for_every_level() {
+    let mut schema_parser = SchemaParser::new();
+    schema_parser
+      .write_json(r#"{"levels": [[[1, "a"], [2.5]], [3, [true]], "x"]}"#)
+      .unwrap();
+    schema_parser
+      .write_json(r#"{"levels": [[[null]]]}"#)
+      .unwrap();
+    let schema = schema_parser.flush();
+    let probabilities = |field_type: &FieldType| {
+      let mut probabilities: Vec<(String, f32)> = field_type
+        .types
+        .iter()
+        .map(|(name, t)| (name.to_string(), t.probability))
+        .collect();
+      probabilities.sort_by(|a, b| a.0.cmp(&b.0));
+      probabilities
+    };
+    let p = |name: &str, probability| (name.to_string(), probability);
+
+    // 4 elements in the outer arrays
+    let outer = &schema.fields["levels"].types["Array"];
+    assert_eq!(outer.lengths, vec![3, 1]);
+    assert_eq!(
+      probabilities(outer),
+      vec![p("Array", 0.75), p("String", 0.25)]
+    );
+    // 5 elements in the 3 arrays inside them
+    let middle = &outer.types["Array"];
+    assert_eq!(middle.lengths, vec![2, 2, 1]);
+    assert_eq!(
+      probabilities(middle),
+      vec![p("Array", 0.8), p("Int32", 0.2)]
+    );
+    // 5 elements of 5 types in the 4 innermost arrays
+    let inner = &middle.types["Array"];
+    assert_eq!(inner.lengths, vec![2, 1, 1, 1]);
+    assert_eq!(
+      probabilities(inner),
+      vec![
+        p("Boolean", 0.2),
+        p("Double", 0.2),
+        p("Int32", 0.2),
+        p("Null", 0.2),
+        p("String", 0.2)
+      ]
+    );
+    assert!(inner.types.values().all(|t| t.types.is_empty()));
+  }
+
   #[test]
   fn it_combines_arrays_of_documents() {
     let mut schema_parser = SchemaParser::new();
@@ -712,15 +2121,57 @@ mod tests {
     schema_parser.write_json(vec_json1).unwrap();
     schema_parser.write_json(vec_json2).unwrap();
     println!("{:?}", schema_parser);
-    // assert_eq!(schema_parser.fields.len(), 1);
-    // let field = schema_parser.fields.get("animals");
-    // if let Some(field) = field {
-    //   assert_eq!(field.types.len(), 1);
-    //   let field_type = field.types.get("Array");
-    //   if let Some(field_type) = field_type {
-    //     assert_eq!(field_type.values.len(), 4);
-    //   }
-    // }
+    let types = |field: &Field| {
+      let mut types: Vec<String> = field.types.keys().cloned().collect();
+      types.sort();
+      types
+    };
+    assert_eq!(
+      types(&schema_parser.fields["saleDate"]),
+      vec!["UtcDatetime"]
+    );
+    let customer = schema_parser.fields["customer"].types["Document"]
+      .schema
+      .as_ref()
+      .unwrap();
+    assert_eq!(types(&customer.fields["age"]), vec!["Int32"]);
+    let item = schema_parser.fields["items"].types["Array"].types["Document"]
+      .schema
+      .as_ref()
+      .unwrap();
+    assert_eq!(types(&item.fields["quantity"]), vec!["Int32"]);
+  }
+
+  #[test]
+  fn it_parses_extended_json() {
+    let mut schema_parser = SchemaParser::new();
+    // canonical
+    let json = r#"{
+      "_id": {"$oid": "5d6e2e2a1c9d440000a1b2c3"},
+      "count": {"$numberLong": "42"},
+      "price": {"$numberDecimal": "9.99"},
+      "ratio": {"$numberDouble": "0.5"}
+    }"#;
+    schema_parser.write_json(json).unwrap();
+    // relaxed
+    let json = r#"{
+      "_id": {"$oid": "5d6e2e2a1c9d440000a1b2c4"},
+      "count": 3000000000,
+      "created": {"$date": "2019-09-03T09:24:58.000Z"},
+      "ratio": 0.25
+    }"#;
+    schema_parser.write_json(json).unwrap();
+    let bson_types = |path: &str| {
+      let mut types: Vec<String> =
+        schema_parser.fields[path].types.keys().cloned().collect();
+      types.sort();
+      types
+    };
+    assert_eq!(bson_types("_id"), vec!["ObjectId"]);
+    assert_eq!(bson_types("count"), vec!["Long"]);
+    assert_eq!(bson_types("price"), vec!["Decimal128"]);
+    assert_eq!(bson_types("ratio"), vec!["Double"]);
+    assert_eq!(bson_types("created"), vec!["UtcDatetime"]);
   }
 
   #[test]
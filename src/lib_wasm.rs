@@ -1,28 +1,36 @@
-use super::SchemaParser;
-use failure::format_err;
+use super::{ErrorKind, Result, SchemaParser};
 use js_sys::{Object, Uint8Array};
 use wasm_bindgen::prelude::*;
 
-use console_error_panic_hook;
-
 // Need to wrap schema parser impl for wasm suppport.
 // Here we are wrapping the exported to JS land methods and mathing on Result to
 // turn the error message to JsValue.
 #[wasm_bindgen]
 impl SchemaParser {
-  /// Wrapper method for `SchemaParser::new()` to be used in JavaScript.
+  /// Wrapper method for `SchemaParser::new()` and
+  /// `SchemaParser::with_options()` to be used in JavaScript. Options are
+  /// optional, and only need the fields that differ from the defaults.
   /// `wasm_bindgen(js_name = "new")`
   ///
   /// ```js, ignore
   /// import { SchemaParser } from "mongodb-schema-parser";
   ///
   /// var schemaParser = new SchemaParser()
+  /// var shallowParser = new SchemaParser({ max_depth: 2, max_values: 100 })
   /// ````
   #[wasm_bindgen(constructor)]
   #[wasm_bindgen(js_name = "new")]
-  pub fn wasm_new() -> Self {
+  #[allow(deprecated)]
+  pub fn wasm_new(options: JsValue) -> Result<SchemaParser, JsValue> {
     console_error_panic_hook::set_once();
-    Self::new()
+    if options.is_undefined() || options.is_null() {
+      return Ok(Self::new());
+    }
+    match options.into_serde() {
+      Err(e) => Err(JsValue::from_str(&format!("{}", e))),
+      Ok(options) => Self::with_options(options)
+        .map_err(|e| JsValue::from_str(&format!("{}", e))),
+    }
   }
 
   /// Wrapper method for `schema_parser.write_json()` to be used in JavaScript.
@@ -95,13 +103,80 @@ impl SchemaParser {
     }
   }
 
-  fn to_js_object(&self) -> Result<Object, failure::Error> {
-    let js_val = JsValue::from_serde(&serde_json::to_value(&self)?)?;
+  /// Wrapper method for `schema_parser.into_compass_json()` to be used in
+  /// JavaScript. Returns the schema in the same format as the `mongodb-schema`
+  /// npm package.
+  /// `wasm_bindgen(js_name = "toCompassJson")`
+  ///
+  /// ```js, ignore
+  /// import { SchemaParser } from "mongodb-schema-parser"
+  ///
+  /// var schemaParser = new SchemaParser()
+  /// var json = "{"name": "Nori", "type": "Cat"}"
+  /// schemaParser.writeJson(json)
+  /// var result = schemaParser.toCompassJson()
+  /// console.log(result) //
+  /// ````
+  #[wasm_bindgen(js_name = "toCompassJson")]
+  pub fn wasm_into_compass_json(self) -> Result<String, JsValue> {
+    match self.into_compass_json() {
+      Err(e) => Err(JsValue::from_str(&format!("{}", e))),
+      Ok(val) => Ok(val),
+    }
+  }
+
+  /// Wrapper method for `schema_parser.check_document()` to be used in
+  /// JavaScript. Takes a document as a json string and returns its deviations
+  /// from the schema as a json string.
+  /// `wasm_bindgen(js_name = "checkJson")`
+  ///
+  /// ```js, ignore
+  /// import { SchemaParser } from "mongodb-schema-parser"
+  ///
+  /// var schemaParser = new SchemaParser()
+  /// schemaParser.writeJson("{"name": "Nori", "type": "Cat"}")
+  /// var deviations = JSON.parse(schemaParser.checkJson("{"name": "Rey"}"))
+  /// ````
+  #[wasm_bindgen(js_name = "checkJson")]
+  pub fn wasm_check_json(&self, json: &str) -> Result<String, JsValue> {
+    let checked = Self::parse_json(json)
+      .and_then(|doc| Ok(serde_json::to_string(&self.check_document(&doc))?));
+    match checked {
+      Err(e) => Err(JsValue::from_str(&format!("{}", e))),
+      Ok(val) => Ok(val),
+    }
+  }
+
+  /// Wrapper method for `schema_parser.diff()` to be used in JavaScript.
+  /// Returns the diff as a json string.
+  /// `wasm_bindgen(js_name = "diff")`
+  ///
+  /// ```js, ignore
+  /// import { SchemaParser } from "mongodb-schema-parser"
+  ///
+  /// var before = new SchemaParser()
+  /// before.writeJson("{"name": "Nori", "type": "Cat"}")
+  /// var after = new SchemaParser()
+  /// after.writeJson("{"name": "Rey", "breed": "Viszla"}")
+  /// var diff = JSON.parse(before.diff(after))
+  /// ````
+  #[wasm_bindgen(js_name = "diff")]
+  pub fn wasm_diff(&self, other: &SchemaParser) -> Result<String, JsValue> {
+    match self.diff(other).to_json() {
+      Err(e) => Err(JsValue::from_str(&format!("{}", e))),
+      Ok(val) => Ok(val),
+    }
+  }
+
+  #[allow(deprecated)]
+  fn to_js_object(&self) -> Result<Object> {
+    let js_val = JsValue::from_serde(&serde_json::to_value(self)?)?;
     let js_obj = Object::try_from(&js_val);
     if let Some(js_obj) = js_obj {
       Ok(js_obj.clone())
     } else {
-      Err(format_err!("Cannot create JavaScript Object from Schema."))
+      let message = "Cannot create JavaScript Object from Schema.";
+      Err(ErrorKind::Other(message.to_string()).into())
     }
   }
 }
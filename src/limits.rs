@@ -0,0 +1,239 @@
+use super::projection::is_selected;
+use super::{
+  doc, Bson, Document, Error, ErrorKind, Field, Result, SchemaParserOptions,
+};
+use crate::options::OnLimit;
+use std::io;
+
+// Returns an error for the first limit of `options` that `doc` goes past,
+// so that it can be rejected before any of it is written. Documents are
+// only checked with `OnLimit::Error`; otherwise analysis is cut short
+// while writing.
+pub(crate) fn check(
+  doc: &Document,
+  options: &SchemaParserOptions,
+) -> Result<()> {
+  if options.on_limit != OnLimit::Error {
+    return Ok(());
+  }
+  if let Some(max) = options.max_document_bytes {
+    if document_size(doc) > max {
+      return Err(exceeded("max_document_bytes", max));
+    }
+  }
+  check_document(doc, None, 1, options)
+}
+
+fn check_document(
+  doc: &Document,
+  path: Option<&str>,
+  depth: usize,
+  options: &SchemaParserOptions,
+) -> Result<()> {
+  if let Some(max) = options.max_fields {
+    if doc.len() > max {
+      let error = exceeded("max_fields", max);
+      return Err(match path {
+        Some(path) => error.in_field(path.to_string()),
+        None => error,
+      });
+    }
+  }
+  for (key, value) in doc {
+    let path = Field::get_path(key.to_owned(), path.map(str::to_string));
+    if is_selected(options, &path, value) {
+      check_value(value, &path, depth, options)?;
+    }
+  }
+  Ok(())
+}
+
+fn check_value(
+  value: &Bson,
+  path: &str,
+  depth: usize,
+  options: &SchemaParserOptions,
+) -> Result<()> {
+  let too_deep = |empty: bool| match options.max_depth {
+    Some(max) if depth >= max && !empty => {
+      Err(exceeded("max_depth", max).in_field(path.to_string()))
+    }
+    _ => Ok(()),
+  };
+  match value {
+    Bson::Document(doc) => {
+      too_deep(doc.is_empty())?;
+      check_document(doc, Some(path), depth + 1, options)
+    }
+    Bson::Array(arr) if options.analyse_arrays => {
+      too_deep(arr.is_empty())?;
+      if let Some(max) = options.max_array_elements {
+        if arr.len() > max {
+          let error = exceeded("max_array_elements", max);
+          return Err(error.in_field(path.to_string()));
+        }
+      }
+      arr
+        .iter()
+        .try_for_each(|value| check_value(value, path, depth + 1, options))
+    }
+    _ => Ok(()),
+  }
+}
+
+fn exceeded(limit: &'static str, max: usize) -> Error {
+  ErrorKind::LimitExceeded { limit, max }.into()
+}
+
+// Size of `doc` encoded as BSON.
+pub(crate) fn document_size(doc: &Document) -> usize {
+  // length and trailing nul byte
+  5 + doc
+    .iter()
+    .map(|(key, value)| element_size(key, value))
+    .sum::<usize>()
+}
+
+// Size of a `key` and `value` pair inside a BSON document.
+pub(crate) fn element_size(key: &str, value: &Bson) -> usize {
+  // element type, key and its nul byte
+  2 + key.len() + value_size(value)
+}
+
+fn value_size(value: &Bson) -> usize {
+  // strings are preceded by their length and followed by a nul byte
+  let string = |s: &str| 5 + s.len();
+  match value {
+    Bson::Double(_) | Bson::Int64(_) | Bson::Timestamp(_) => 8,
+    Bson::DateTime(_) => 8,
+    Bson::Int32(_) => 4,
+    Bson::Boolean(_) => 1,
+    Bson::Decimal128(_) => 16,
+    Bson::ObjectId(_) => 12,
+    Bson::Null | Bson::Undefined | Bson::MaxKey | Bson::MinKey => 0,
+    Bson::String(s) | Bson::JavaScriptCode(s) | Bson::Symbol(s) => string(s),
+    Bson::RegularExpression(regex) => {
+      regex.pattern.len() + regex.options.len() + 2
+    }
+    Bson::JavaScriptCodeWithScope(code) => {
+      4 + string(&code.code) + document_size(&code.scope)
+    }
+    Bson::Binary(binary) => {
+      // the old binary subtype repeats the length
+      let old = binary.subtype == bson::spec::BinarySubtype::BinaryOld;
+      5 + binary.bytes.len() + if old { 4 } else { 0 }
+    }
+    Bson::Document(doc) => document_size(doc),
+    Bson::Array(arr) => {
+      5 + arr
+        .iter()
+        .enumerate()
+        .map(|(i, value)| element_size(&i.to_string(), value))
+        .sum::<usize>()
+    }
+    // the namespace of a DbPointer is private, so it is measured encoded
+    Bson::DbPointer(_) => {
+      let mut size = ByteCount(0);
+      let wrapped = doc! {"": value.clone()};
+      match wrapped.to_writer(&mut size) {
+        // wrapper length, element type, empty key and trailing nul byte
+        Ok(()) => size.0 - 7,
+        Err(_) => 0,
+      }
+    }
+  }
+}
+
+struct ByteCount(usize);
+
+impl io::Write for ByteCount {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.0 += buf.len();
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use bson::oid::ObjectId;
+  use bson::spec::BinarySubtype;
+  use bson::{Binary, DateTime, JavaScriptCodeWithScope, Regex, Timestamp};
+
+  fn limited(on_limit: OnLimit) -> SchemaParserOptions {
+    SchemaParserOptions {
+      on_limit,
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn it_measures_documents() {
+    let doc = doc! {
+      "double": 1.5,
+      "string": "Nori",
+      "doc": {"a": 1, "b": [true, null]},
+      "binary": Binary { subtype: BinarySubtype::Generic, bytes: vec![1, 2] },
+      "old": Binary { subtype: BinarySubtype::BinaryOld, bytes: vec![1] },
+      "id": ObjectId::new(),
+      "date": DateTime::now(),
+      "long": 2i64,
+      "regex": Regex { pattern: "^a".to_string(), options: "i".to_string() },
+      "code": JavaScriptCodeWithScope {
+        code: "x".to_string(),
+        scope: doc! {"x": 1},
+      },
+      "timestamp": Timestamp { time: 1, increment: 2 },
+      "min": Bson::MinKey,
+    };
+    let mut bytes = Vec::new();
+    doc.to_writer(&mut bytes).unwrap();
+    assert_eq!(document_size(&doc), bytes.len());
+  }
+
+  #[test]
+  fn it_checks_limits() {
+    let doc = doc! {"owner": {"address": {"city": "Berlin"}}, "toys": [1, 2]};
+    let mut options = limited(OnLimit::Error);
+    assert!(check(&doc, &options).is_ok());
+
+    options.max_depth = Some(2);
+    let error = check(&doc, &options).unwrap_err();
+    assert_eq!(
+      error.to_string(),
+      "field 'owner.address': exceeds the max_depth limit of 2"
+    );
+
+    options.max_depth = None;
+    options.max_array_elements = Some(1);
+    let error = check(&doc, &options).unwrap_err();
+    assert_eq!(error.path(), Some("toys"));
+
+    options.max_array_elements = None;
+    options.max_fields = Some(1);
+    let error = check(&doc, &options).unwrap_err();
+    assert_eq!(error.to_string(), "exceeds the max_fields limit of 1");
+
+    options.max_fields = None;
+    options.max_document_bytes = Some(16);
+    let error = check(&doc, &options).unwrap_err();
+    assert!(matches!(
+      error.kind(),
+      ErrorKind::LimitExceeded {
+        limit: "max_document_bytes",
+        max: 16
+      }
+    ));
+
+    // documents are truncated while writing instead
+    let options = SchemaParserOptions {
+      max_fields: Some(1),
+      ..limited(OnLimit::Truncate)
+    };
+    assert!(check(&doc, &options).is_ok());
+  }
+}
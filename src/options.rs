@@ -0,0 +1,102 @@
+use crate::redact::Redaction;
+use crate::{ErrorKind, Result};
+
+/// What `SchemaParser::with_options` analyses and keeps. Deserializes from a
+/// partial object, so the JavaScript side can pass only the options it
+/// changes, i.e. `new SchemaParser({ max_depth: 2 })`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct SchemaParserOptions {
+  /// Keep at most this many values of every type; `Some(0)` keeps none.
+  /// `unique` and `has_duplicates` only count the values that were kept.
+  pub max_values: Option<usize>,
+  /// Top level fields are at depth 1. Subdocuments and arrays found at
+  /// `max_depth` are counted with their type, but their fields and
+  /// elements are not analysed. Defaults to 100, the nesting limit of the
+  /// server. `None` analyses any depth, which lets documents built in Rust
+  /// nest deep enough to overflow the stack.
+  pub max_depth: Option<usize>,
+  /// Analyse at most this many fields of every document and subdocument.
+  pub max_fields: Option<usize>,
+  /// Analyse at most this many elements of every array.
+  pub max_array_elements: Option<usize>,
+  /// Analyse the fields of a document up to this many bytes, measured as
+  /// BSON.
+  pub max_document_bytes: Option<usize>,
+  /// What happens to documents going past `max_depth`, `max_fields`,
+  /// `max_array_elements` or `max_document_bytes`.
+  pub on_limit: OnLimit,
+  /// Analyse the elements of arrays. Otherwise arrays are only counted,
+  /// without element types, values or lengths.
+  pub analyse_arrays: bool,
+  /// Compute `unique` and `has_duplicates` of every type.
+  pub unique: bool,
+  /// Only analyse fields whose dotted path matches one of these patterns,
+  /// and the documents and arrays leading to them. `*` matches within a
+  /// segment and `**` any number of segments, i.e. `owner.*` or
+  /// `**.email`. Every field is analysed if this is empty.
+  pub include: Vec<String>,
+  /// Don't analyse fields whose dotted path matches one of these patterns,
+  /// i.e. `payload.**` or `*.internal`. Takes precedence over `include`.
+  pub exclude: Vec<String>,
+  /// Redact the values of fields whose dotted path matches one of these
+  /// patterns, and of everything nested in them, i.e. `**.email`.
+  pub redact: Vec<String>,
+  /// Also redact fields with a name listed in `SENSITIVE_NAMES`.
+  pub redact_sensitive: bool,
+  /// What the values of redacted fields are replaced with.
+  pub redaction: Redaction,
+  /// Key of the hashes of `Redaction::Hash`, which needs one. Keep it secret:
+  /// anyone with it can hash guesses, i.e. likely emails, and compare them to
+  /// the schema.
+  pub salt: String,
+}
+
+impl SchemaParserOptions {
+  pub(crate) const DEFAULT: SchemaParserOptions = SchemaParserOptions {
+    max_values: None,
+    max_depth: Some(100),
+    max_fields: None,
+    max_array_elements: None,
+    max_document_bytes: None,
+    on_limit: OnLimit::Truncate,
+    analyse_arrays: true,
+    unique: true,
+    include: Vec::new(),
+    exclude: Vec::new(),
+    redact: Vec::new(),
+    redact_sensitive: false,
+    redaction: Redaction::Drop,
+    salt: String::new(),
+  };
+
+  // Hashes keyed with an empty salt can be reversed by hashing likely
+  // values, so they would only look redacted.
+  pub(crate) fn check(&self) -> Result<()> {
+    if self.redaction == Redaction::Hash && self.salt.is_empty() {
+      let message = "Redaction::Hash needs a salt".to_string();
+      return Err(ErrorKind::InvalidOptions(message).into());
+    }
+    Ok(())
+  }
+}
+
+impl Default for SchemaParserOptions {
+  fn default() -> Self {
+    SchemaParserOptions::DEFAULT
+  }
+}
+
+/// What happens to a document going past one of the limits of
+/// `SchemaParserOptions`, i.e. `max_depth`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OnLimit {
+  /// Analyse the document up to the limit, and count it as `truncated` in
+  /// the type, or as `truncated_documents` in the schema, it was cut short
+  /// in.
+  Truncate,
+  /// Reject the whole document with `ErrorKind::LimitExceeded`, naming the
+  /// field that went past the limit. Nothing of it is written.
+  Error,
+}
@@ -0,0 +1,353 @@
+use super::field_type::{BINARY, STRING};
+use super::redact::is_sensitive;
+use super::{ratio, Bson, FieldType, HashMap, Result, SchemaParser};
+use bson::spec::BinarySubtype;
+use std::fmt::{self, Display};
+use std::net::IpAddr;
+
+/// Kinds of personal data recognised in string and binary values.
+#[derive(
+  Serialize,
+  Deserialize,
+  Debug,
+  Clone,
+  Copy,
+  PartialEq,
+  Eq,
+  Hash,
+  PartialOrd,
+  Ord,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum PiiKind {
+  Email,
+  /// Numbers with a leading `+`, or with at least 10 digits and separators,
+  /// i.e. `+49 30 1234567` or `(555) 123-4567`.
+  Phone,
+  /// 13 to 19 digits that pass the Luhn check.
+  CreditCard,
+  /// US Social Security and UK National Insurance numbers.
+  NationalId,
+  /// IPv4 and IPv6 addresses.
+  IpAddress,
+}
+
+impl Display for PiiKind {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let name = match self {
+      PiiKind::Email => "email",
+      PiiKind::Phone => "phone number",
+      PiiKind::CreditCard => "credit card number",
+      PiiKind::NationalId => "national ID",
+      PiiKind::IpAddress => "IP address",
+    };
+    f.write_str(name)
+  }
+}
+
+/// Fields that likely hold personal data, as returned by `SchemaParser::pii`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct PiiReport {
+  pub fields: Vec<PiiField>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PiiField {
+  pub path: String,
+  /// Kinds of personal data found in the values of this field, most common
+  /// first.
+  pub kinds: Vec<PiiMatch>,
+  /// Whether the name of this field is listed in `SENSITIVE_NAMES`, whatever
+  /// its values look like.
+  pub sensitive_name: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PiiMatch {
+  pub kind: PiiKind,
+  pub count: usize,
+  /// Share of the string and binary values of this field that match.
+  pub probability: f32,
+}
+
+impl PiiReport {
+  /// Returns `true` if no field looks like it holds personal data.
+  pub fn is_empty(&self) -> bool {
+    self.fields.is_empty()
+  }
+
+  /// Returns the report as a serde_json string.
+  pub fn to_json(&self) -> Result<String> {
+    Ok(serde_json::to_string(self)?)
+  }
+
+  pub(crate) fn new(schema: &SchemaParser) -> Self {
+    let mut fields = Vec::new();
+    schema.walk_fields(&mut |field, _| {
+      let mut scanned = 0;
+      let mut matches = HashMap::new();
+      for field_type in field.types.values() {
+        count_matches(field_type, &mut scanned, &mut matches);
+      }
+      let sensitive_name = is_sensitive(&field.name);
+      if matches.is_empty() && !sensitive_name {
+        return;
+      }
+      let mut kinds: Vec<PiiMatch> = matches
+        .into_iter()
+        .map(|(kind, count)| PiiMatch {
+          kind,
+          count,
+          probability: ratio(count, scanned),
+        })
+        .collect();
+      kinds.sort_by(|a, b| b.count.cmp(&a.count).then(a.kind.cmp(&b.kind)));
+      fields.push(PiiField {
+        path: field.path.to_string(),
+        kinds,
+        sensitive_name,
+      });
+    });
+    fields.sort_by(|a, b| a.path.cmp(&b.path));
+    PiiReport { fields }
+  }
+}
+
+// Adds up the string and binary values of `field_type`, and of the types of
+// array elements in it, and how many of them matched every kind.
+fn count_matches(
+  field_type: &FieldType,
+  scanned: &mut usize,
+  matches: &mut HashMap<PiiKind, usize>,
+) {
+  if field_type.bson_type == STRING || field_type.bson_type == BINARY {
+    *scanned += field_type.count;
+  }
+  for (kind, count) in &field_type.pii {
+    *matches.entry(*kind).or_insert(0) += count;
+  }
+  for element_type in field_type.types.values() {
+    count_matches(element_type, scanned, matches);
+  }
+}
+
+impl Display for PiiReport {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.is_empty() {
+      return writeln!(f, "no personal data found");
+    }
+    for field in &self.fields {
+      let mut findings: Vec<String> = field
+        .kinds
+        .iter()
+        .map(|found| {
+          format!("{} ({:.2}%)", found.kind, found.probability * 100.0)
+        })
+        .collect();
+      if field.sensitive_name {
+        findings.push("name suggests personal data".to_string());
+      }
+      writeln!(f, "{}: {}", field.path, findings.join(", "))?;
+    }
+    Ok(())
+  }
+}
+
+// Kind of personal data `value` looks like, if any. Binary values are read
+// as UTF-8 text, unless they are encrypted already.
+pub(crate) fn detect(value: &Bson) -> Option<PiiKind> {
+  match value {
+    Bson::String(string) => detect_str(string),
+    Bson::Binary(binary) if binary.subtype != BinarySubtype::Encrypted => {
+      std::str::from_utf8(&binary.bytes).ok().and_then(detect_str)
+    }
+    _ => None,
+  }
+}
+
+fn detect_str(value: &str) -> Option<PiiKind> {
+  let value = value.trim();
+  // emails, the longest of these kinds, are at most 254 characters
+  if value.is_empty() || value.len() > 254 {
+    return None;
+  }
+  if is_email(value) {
+    Some(PiiKind::Email)
+  } else if is_national_id(value) {
+    Some(PiiKind::NationalId)
+  } else if is_credit_card(value) {
+    Some(PiiKind::CreditCard)
+  } else if value.parse::<IpAddr>().is_ok() {
+    Some(PiiKind::IpAddress)
+  } else if is_phone(value) {
+    Some(PiiKind::Phone)
+  } else {
+    None
+  }
+}
+
+fn is_email(value: &str) -> bool {
+  let (local, domain) = match value.split_once('@') {
+    Some(parts) => parts,
+    None => return false,
+  };
+  let labels: Vec<&str> = domain.split('.').collect();
+  let tld = labels[labels.len() - 1];
+  !local.is_empty()
+    && !value.chars().any(|c| c.is_whitespace() || c == ',')
+    && !domain.contains('@')
+    && labels.len() > 1
+    && labels.iter().all(|label| !label.is_empty())
+    && tld.len() > 1
+    && tld.chars().all(char::is_alphabetic)
+}
+
+// `123-45-6789`, leaving out numbers that are never issued, or
+// `AB 12 34 56 C`.
+fn is_national_id(value: &str) -> bool {
+  let groups: Vec<&str> = value.split('-').collect();
+  if let [area, group, serial] = groups.as_slice() {
+    let digits = |s: &str, len| s.len() == len && is_digits(s);
+    return digits(area, 3)
+      && digits(group, 2)
+      && digits(serial, 4)
+      && *area != "000"
+      && *area != "666"
+      && !area.starts_with('9')
+      && *group != "00"
+      && *serial != "0000";
+  }
+  let nino: Vec<char> = value.chars().filter(|c| *c != ' ').collect();
+  nino.len() == 9
+    && nino[..2].iter().all(char::is_ascii_uppercase)
+    && nino[2..8].iter().all(char::is_ascii_digit)
+    && ('A'..='D').contains(&nino[8])
+}
+
+fn is_credit_card(value: &str) -> bool {
+  if !value
+    .chars()
+    .all(|c| c.is_ascii_digit() || c == ' ' || c == '-')
+  {
+    return false;
+  }
+  let digits: Vec<u32> = value.chars().filter_map(|c| c.to_digit(10)).collect();
+  // issuers start their numbers with 2 to 6
+  (13..=19).contains(&digits.len())
+    && (2..=6).contains(&digits[0])
+    && luhn(&digits)
+}
+
+// `u32::is_multiple_of` needs Rust 1.87
+#[allow(clippy::manual_is_multiple_of)]
+fn luhn(digits: &[u32]) -> bool {
+  let sum: u32 = digits
+    .iter()
+    .rev()
+    .enumerate()
+    .map(|(i, digit)| match (i % 2, digit * 2) {
+      (1, double) if double > 9 => double - 9,
+      (1, double) => double,
+      _ => *digit,
+    })
+    .sum();
+  sum % 10 == 0
+}
+
+fn is_phone(value: &str) -> bool {
+  let (international, number) = match value.strip_prefix('+') {
+    Some(number) => (true, number),
+    None => (false, value),
+  };
+  if !number
+    .chars()
+    .all(|c| c.is_ascii_digit() || " -.()".contains(c))
+  {
+    return false;
+  }
+  let digits = number.chars().filter(char::is_ascii_digit).count();
+  let separated = digits < number.len();
+  // numbers without separators are more likely ids or timestamps
+  if international {
+    (8..=15).contains(&digits)
+  } else {
+    separated && (10..=15).contains(&digits)
+  }
+}
+
+fn is_digits(value: &str) -> bool {
+  value.chars().all(|c| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use bson::{doc, Binary};
+
+  fn detect(value: &str) -> Option<PiiKind> {
+    super::detect(&Bson::String(value.to_string()))
+  }
+
+  #[test]
+  fn it_detects_values() {
+    assert_eq!(detect("irina@example.com"), Some(PiiKind::Email));
+    assert_eq!(detect("+49 30 1234567"), Some(PiiKind::Phone));
+    assert_eq!(detect("(555) 123-4567"), Some(PiiKind::Phone));
+    assert_eq!(detect("4111 1111 1111 1111"), Some(PiiKind::CreditCard));
+    assert_eq!(detect("123-45-6789"), Some(PiiKind::NationalId));
+    assert_eq!(detect("AB 12 34 56 C"), Some(PiiKind::NationalId));
+    assert_eq!(detect("192.168.0.1"), Some(PiiKind::IpAddress));
+    assert_eq!(detect("2001:db8::1"), Some(PiiKind::IpAddress));
+
+    assert_eq!(detect("4111 1111 1111 1112"), None);
+    assert_eq!(detect("666-45-6789"), None);
+    assert_eq!(detect("2020-01-01"), None);
+    assert_eq!(detect("1600000000"), None);
+    assert_eq!(detect("@nori"), None);
+    assert_eq!(detect("Nori"), None);
+
+    let binary = |subtype| {
+      Bson::Binary(Binary {
+        subtype,
+        bytes: b"irina@example.com".to_vec(),
+      })
+    };
+    let generic = binary(BinarySubtype::Generic);
+    assert_eq!(super::detect(&generic), Some(PiiKind::Email));
+    assert_eq!(super::detect(&binary(BinarySubtype::Encrypted)), None);
+  }
+
+  #[test]
+  fn it_reports_fields() {
+    let mut schema_parser = SchemaParser::new();
+    for i in 0..4 {
+      let contact = if i == 0 { "n/a" } else { "irina@example.com" };
+      schema_parser
+        .write_bson(doc! {
+          "name": "Nori",
+          "owner": {"contacts": [contact, "+49 30 1234567"]},
+          "dob": "2015-06-01",
+        })
+        .unwrap();
+    }
+    let report = schema_parser.pii();
+    assert_eq!(report.fields.len(), 2);
+    let dob = &report.fields[0];
+    assert_eq!(dob.path, "dob");
+    assert!(dob.kinds.is_empty());
+    assert!(dob.sensitive_name);
+
+    let contacts = &report.fields[1];
+    assert_eq!(contacts.path, "owner.contacts");
+    assert!(!contacts.sensitive_name);
+    assert_eq!(contacts.kinds[0].kind, PiiKind::Phone);
+    assert_eq!(contacts.kinds[0].count, 4);
+    assert_eq!(contacts.kinds[1].kind, PiiKind::Email);
+    assert_eq!(contacts.kinds[1].probability, 0.375);
+    assert_eq!(
+      report.to_string(),
+      "dob: name suggests personal data\nowner.contacts: phone number \
+       (50.00%), email (37.50%)\n"
+    );
+  }
+}
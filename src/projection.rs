@@ -0,0 +1,147 @@
+use super::{Bson, SchemaParserOptions};
+use std::str::Split;
+
+// Whether the field at `path` holding `value` is analysed with the `include`
+// and `exclude` patterns of `options`. Documents and arrays leading to an
+// included field are analysed, but only for the fields that lead there too.
+pub(crate) fn is_selected(
+  options: &SchemaParserOptions,
+  path: &str,
+  value: &Bson,
+) -> bool {
+  if options.exclude.iter().any(|pattern| matches(pattern, path)) {
+    return false;
+  }
+  let nests = matches!(value, Bson::Document(_) | Bson::Array(_));
+  options.include.is_empty()
+    || options.include.iter().any(|pattern| {
+      matches(pattern, path)
+        || nests && leads_to(segments(pattern), segments(path))
+    })
+}
+
+// `pattern` matches `path` or one of the documents it is nested in.
+pub(crate) fn matches(pattern: &str, path: &str) -> bool {
+  covers(segments(pattern), segments(path))
+}
+
+fn segments(path: &str) -> Split<'_, char> {
+  path.split('.')
+}
+
+// `pattern` matches `path` or one of the documents it is nested in. A `**`
+// matches any number of segments, a trailing one at least one, so that
+// `payload.**` covers the fields of `payload`, but not `payload` itself.
+fn covers(mut pattern: Split<'_, char>, mut path: Split<'_, char>) -> bool {
+  match pattern.next() {
+    None => true,
+    Some("**") => {
+      let trailing = pattern.clone().next().is_none();
+      if !trailing && covers(pattern.clone(), path.clone()) {
+        return true;
+      }
+      while path.next().is_some() {
+        if covers(pattern.clone(), path.clone()) {
+          return true;
+        }
+      }
+      false
+    }
+    Some(segment) => match path.next() {
+      Some(name) => matches_segment(segment, name) && covers(pattern, path),
+      None => false,
+    },
+  }
+}
+
+// `pattern` can match a field nested in `path`.
+fn leads_to(mut pattern: Split<'_, char>, mut path: Split<'_, char>) -> bool {
+  match (pattern.next(), path.next()) {
+    (Some(_), None) => true,
+    (None, _) => false,
+    (Some("**"), Some(_)) => true,
+    (Some(segment), Some(name)) => {
+      matches_segment(segment, name) && leads_to(pattern, path)
+    }
+  }
+}
+
+// A `*` in `pattern` matches any characters of a single segment.
+fn matches_segment(pattern: &str, name: &str) -> bool {
+  let mut parts = pattern.split('*');
+  let first = parts.next().unwrap_or("");
+  let mut rest = match name.strip_prefix(first) {
+    Some(rest) => rest,
+    None => return false,
+  };
+  let mut parts: Vec<&str> = parts.collect();
+  let last = match parts.pop() {
+    Some(last) => last,
+    // no `*` at all
+    None => return rest.is_empty(),
+  };
+  for part in parts {
+    match rest.find(part) {
+      Some(index) => rest = &rest[index + part.len()..],
+      None => return false,
+    }
+  }
+  rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn options(include: &[&str], exclude: &[&str]) -> SchemaParserOptions {
+    SchemaParserOptions {
+      include: include.iter().map(|p| p.to_string()).collect(),
+      exclude: exclude.iter().map(|p| p.to_string()).collect(),
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn it_matches_segments() {
+    assert!(matches_segment("name", "name"));
+    assert!(!matches_segment("name", "names"));
+    assert!(matches_segment("*", "name"));
+    assert!(matches_segment("user_*", "user_id"));
+    assert!(matches_segment("*_id", "user_id"));
+    assert!(matches_segment("u*_*d", "user_id"));
+    assert!(!matches_segment("*_id_*", "user_id"));
+    assert!(!matches_segment("ab*ba", "aba"));
+  }
+
+  #[test]
+  fn it_excludes_paths() {
+    let options = options(&[], &["payload.**", "*.internal"]);
+    let value = Bson::Int32(1);
+    assert!(is_selected(&options, "payload", &value));
+    assert!(!is_selected(&options, "payload.blob", &value));
+    assert!(!is_selected(&options, "payload.blob.data", &value));
+    assert!(is_selected(&options, "internal", &value));
+    assert!(!is_selected(&options, "meta.internal", &value));
+    assert!(is_selected(&options, "meta.internal_id", &value));
+    assert!(is_selected(&options, "meta.owner.internal", &value));
+  }
+
+  #[test]
+  fn it_includes_paths() {
+    let options = options(&["owner.name", "**.email"], &["archive.**"]);
+    let value = Bson::Int32(1);
+    let doc = Bson::Document(Default::default());
+    assert!(is_selected(&options, "owner", &doc));
+    assert!(!is_selected(&options, "owner", &value));
+    assert!(is_selected(&options, "owner.name", &value));
+    assert!(is_selected(&options, "owner.name.first", &value));
+    assert!(!is_selected(&options, "owner.age", &value));
+    // `**` can lead through any document or array
+    assert!(is_selected(&options, "email", &value));
+    assert!(is_selected(&options, "contact", &doc));
+    assert!(is_selected(&options, "contact", &Bson::Array(vec![])));
+    assert!(!is_selected(&options, "contact", &value));
+    assert!(is_selected(&options, "contact.email", &value));
+    assert!(!is_selected(&options, "archive.email", &value));
+  }
+}
@@ -0,0 +1,471 @@
+use super::{Error, ErrorKind, Location, Result, SchemaParser};
+use bson::raw::{RawBsonRef, RawDocument};
+use bson::Document;
+use serde::de::{self, SeqAccess, Visitor};
+use serde_json::Value;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Cursor, Read};
+
+// Every BSON document starts with its total length as a little-endian i32,
+// followed by at least one element list terminator.
+static MIN_DOCUMENT_LENGTH: usize = 5;
+
+// Most documents are far below the 16MB limit of the server.
+static MAX_INITIAL_CAPACITY: usize = 64 * 1024;
+
+/// Deepest nesting of documents and arrays accepted in BSON input, the same
+/// 100 levels the server allows for stored documents. Decoding recurses once
+/// per level, so deeper input has to be rejected before it is decoded.
+pub(crate) static MAX_BSON_DEPTH: usize = 100;
+
+/// Iterates over concatenated BSON documents, as written by `mongodump` for a
+/// collection, together with the byte offset each of them starts at.
+pub(crate) struct BsonReader<R> {
+  reader: R,
+  offset: u64,
+  done: bool,
+}
+
+// What a length prefix can introduce. `mongodump --archive` ends blocks of
+// documents with a length of -1.
+#[derive(Debug)]
+pub(crate) enum Block {
+  Document(Document),
+  Terminator,
+  // The length was valid, so reading can go on with the next document.
+  Malformed {
+    bytes: Vec<u8>,
+    error: bson::de::Error,
+  },
+}
+
+impl Block {
+  // Malformed documents are an error unless they're skipped explicitly.
+  pub(crate) fn strict(self, offset: u64) -> Result<Self> {
+    match self {
+      Block::Malformed { error, .. } => {
+        Err(Error::from(error).at(Location::Offset(offset)))
+      }
+      block => Ok(block),
+    }
+  }
+}
+
+static TERMINATOR: [u8; 4] = [0xff; 4];
+
+static GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+static ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+impl<R: Read> BsonReader<R> {
+  pub(crate) fn new(reader: R) -> Self {
+    Self::with_offset(reader, 0)
+  }
+
+  /// For readers that already had `offset` bytes taken from them, so that
+  /// errors report offsets from the start of the input.
+  pub(crate) fn with_offset(reader: R, offset: u64) -> Self {
+    BsonReader {
+      reader,
+      offset,
+      done: false,
+    }
+  }
+
+  /// Byte offset of the next document.
+  pub(crate) fn offset(&self) -> u64 {
+    self.offset
+  }
+
+  pub(crate) fn read_block(&mut self) -> Result<Option<Block>> {
+    let offset = self.offset;
+    let mut length = [0u8; 4];
+    let read = read_full(&mut self.reader, &mut length)?;
+    if read == 0 {
+      return Ok(None);
+    }
+    if read < length.len() {
+      return Err(truncated(offset));
+    }
+    if length == TERMINATOR {
+      self.offset += length.len() as u64;
+      return Ok(Some(Block::Terminator));
+    }
+
+    let size = i32::from_le_bytes(length);
+    if size < 0 || (size as usize) < MIN_DOCUMENT_LENGTH {
+      return Err(invalid_length(size, offset));
+    }
+    // the buffer grows with what is actually read, so a corrupt length can't
+    // allocate gigabytes up front
+    let size = size as usize;
+    let mut bytes = Vec::with_capacity(size.min(MAX_INITIAL_CAPACITY));
+    bytes.extend_from_slice(&length);
+    let rest = (size - length.len()) as u64;
+    (&mut self.reader).take(rest).read_to_end(&mut bytes)?;
+    if bytes.len() < size {
+      return Err(truncated(offset));
+    }
+    self.offset += bytes.len() as u64;
+
+    match decode(&bytes) {
+      Ok(doc) => Ok(Some(Block::Document(doc))),
+      Err(error) => Ok(Some(Block::Malformed { bytes, error })),
+    }
+  }
+
+  fn read_document(&mut self) -> Result<Option<Document>> {
+    let offset = self.offset;
+    match self.read_block()? {
+      Some(block) => match block.strict(offset)? {
+        Block::Document(doc) => Ok(Some(doc)),
+        _ => Err(invalid_length(-1, offset)),
+      },
+      None => Ok(None),
+    }
+  }
+}
+
+/// Decodes a BSON document, after checking that it nests no deeper than
+/// `MAX_BSON_DEPTH`.
+pub(crate) fn decode(bytes: &[u8]) -> Result<Document, bson::de::Error> {
+  check_depth(bytes)?;
+  Document::from_reader(bytes)
+}
+
+// Walks the raw elements with a stack of its own rather than by recursion, so
+// input of any depth can be checked. Malformed bytes are left for
+// `Document::from_reader` to report.
+fn check_depth(bytes: &[u8]) -> Result<(), bson::de::Error> {
+  let mut stack = match RawDocument::from_bytes(bytes) {
+    Ok(doc) => vec![(1, doc)],
+    Err(_) => return Ok(()),
+  };
+  while let Some((depth, doc)) = stack.pop() {
+    if depth > MAX_BSON_DEPTH {
+      let message = format!("document nested deeper than {}", MAX_BSON_DEPTH);
+      return Err(de::Error::custom(message));
+    }
+    for element in doc.iter() {
+      let nested = match element {
+        Ok((_, RawBsonRef::Document(doc))) => doc,
+        Ok((_, RawBsonRef::Array(arr))) => {
+          match RawDocument::from_bytes(arr.as_bytes()) {
+            Ok(doc) => doc,
+            Err(_) => continue,
+          }
+        }
+        Ok((_, RawBsonRef::JavaScriptCodeWithScope(code))) => code.scope,
+        Ok(_) => continue,
+        Err(_) => break,
+      };
+      stack.push((depth + 1, nested));
+    }
+  }
+  Ok(())
+}
+
+fn truncated(offset: u64) -> Error {
+  let message = "truncated BSON document".to_string();
+  Error::from(ErrorKind::InvalidInput(message)).at(Location::Offset(offset))
+}
+
+pub(crate) fn invalid_length(length: i32, offset: u64) -> Error {
+  let message = format!("invalid BSON document length {}", length);
+  Error::from(ErrorKind::InvalidInput(message)).at(Location::Offset(offset))
+}
+
+impl<R: Read> Iterator for BsonReader<R> {
+  type Item = Result<(u64, Document)>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.done {
+      return None;
+    }
+    let offset = self.offset;
+    match self.read_document() {
+      Ok(Some(doc)) => Some(Ok((offset, doc))),
+      Ok(None) => {
+        self.done = true;
+        None
+      }
+      Err(e) => {
+        // the length prefix can't be trusted anymore, so there is no way to
+        // find the start of the next document.
+        self.done = true;
+        Some(Err(e))
+      }
+    }
+  }
+}
+
+// Writes the elements of a json array into a SchemaParser as they are
+// deserialized, so only one document is held in memory at a time. Errors of
+// the elements themselves are kept in `error`, since serde_json can only
+// return its own error type.
+pub(crate) struct JsonArrayVisitor<'a> {
+  schema_parser: &'a mut SchemaParser,
+  error: &'a mut Option<Error>,
+}
+
+impl<'a> JsonArrayVisitor<'a> {
+  pub(crate) fn new(
+    schema_parser: &'a mut SchemaParser,
+    error: &'a mut Option<Error>,
+  ) -> Self {
+    JsonArrayVisitor {
+      schema_parser,
+      error,
+    }
+  }
+
+  fn fail<E: de::Error>(self, error: Error) -> E {
+    let custom = E::custom(&error);
+    *self.error = Some(error);
+    custom
+  }
+}
+
+impl<'de, 'a> Visitor<'de> for JsonArrayVisitor<'a> {
+  type Value = usize;
+
+  fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "an array of documents")
+  }
+
+  fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<usize, A::Error> {
+    let mut count = 0;
+    let mut index = 0;
+    while let Some(value) = seq.next_element::<Value>()? {
+      let snippet = value.to_string();
+      let written = SchemaParser::parse_value(value)
+        .and_then(|doc| self.schema_parser.write_bson(doc))
+        .map_err(|e| e.at(Location::Element(index)));
+      match written {
+        Ok(()) => count += 1,
+        Err(e) => {
+          if let Err(e) = self.schema_parser.skip(e, &snippet) {
+            return Err(self.fail(e));
+          }
+        }
+      }
+      index += 1;
+    }
+    Ok(count)
+  }
+}
+
+// Wraps gzip or zstd compressed input in a decoder, telling them apart by
+// their magic bytes. Anything else is passed through as is.
+pub(crate) fn decompress<'a, R: Read + 'a>(
+  mut reader: R,
+) -> Result<Box<dyn BufRead + 'a>> {
+  let mut magic = [0u8; 4];
+  let read = read_full(&mut reader, &mut magic)?;
+  // put the magic bytes back in front of the rest of the input
+  let reader = Cursor::new(magic[..read].to_vec()).chain(reader);
+
+  if magic[..read].starts_with(&GZIP_MAGIC) {
+    return gzip(reader);
+  }
+  if magic[..read].starts_with(&ZSTD_MAGIC) {
+    return zstd(reader);
+  }
+  Ok(Box::new(BufReader::new(reader)))
+}
+
+#[cfg(feature = "gzip")]
+fn gzip<'a, R: Read + 'a>(reader: R) -> Result<Box<dyn BufRead + 'a>> {
+  // compressed dumps are often several gzip members concatenated together
+  let decoder = flate2::read::MultiGzDecoder::new(reader);
+  Ok(Box::new(BufReader::new(decoder)))
+}
+
+#[cfg(not(feature = "gzip"))]
+fn gzip<'a, R: Read + 'a>(_reader: R) -> Result<Box<dyn BufRead + 'a>> {
+  let message = "input is gzip compressed, which needs the `gzip` feature";
+  Err(ErrorKind::InvalidInput(message.to_string()).into())
+}
+
+#[cfg(feature = "zstd")]
+fn zstd<'a, R: Read + 'a>(reader: R) -> Result<Box<dyn BufRead + 'a>> {
+  let decoder = zstd::stream::read::Decoder::new(reader)?;
+  Ok(Box::new(BufReader::new(decoder)))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd<'a, R: Read + 'a>(_reader: R) -> Result<Box<dyn BufRead + 'a>> {
+  let message = "input is zstd compressed, which needs the `zstd` feature";
+  Err(ErrorKind::InvalidInput(message.to_string()).into())
+}
+
+// Skips leading whitespace and checks whether the input is a json array, as
+// written by `mongoexport --jsonArray`, rather than newline-delimited json.
+pub(crate) fn starts_with_array<R: BufRead>(
+  reader: &mut R,
+) -> io::Result<bool> {
+  loop {
+    let buf = reader.fill_buf()?;
+    match buf.iter().position(|byte| !byte.is_ascii_whitespace()) {
+      Some(i) => {
+        let is_array = buf[i] == b'[';
+        reader.consume(i);
+        return Ok(is_array);
+      }
+      None if buf.is_empty() => return Ok(false),
+      None => {
+        let len = buf.len();
+        reader.consume(len);
+      }
+    }
+  }
+}
+
+// Like `Read::read_exact`, but returns how much was read instead of failing
+// on EOF, so a clean end of input can be told apart from a truncated document.
+pub(crate) fn read_full<R: Read>(
+  reader: &mut R,
+  buf: &mut [u8],
+) -> io::Result<usize> {
+  let mut read = 0;
+  while read < buf.len() {
+    match reader.read(&mut buf[read..]) {
+      Ok(0) => break,
+      Ok(n) => read += n,
+      Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+      Err(e) => return Err(e),
+    }
+  }
+  Ok(read)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use bson::doc;
+
+  fn bytes(docs: &[Document]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for doc in docs {
+      doc.to_writer(&mut bytes).unwrap();
+    }
+    bytes
+  }
+
+  #[test]
+  fn it_reads_concatenated_documents() {
+    let docs = vec![doc! {"name": "Nori"}, doc! {"name": "Rey", "age": 2}];
+    let bytes = bytes(&docs);
+    let read: Vec<(u64, Document)> = BsonReader::new(bytes.as_slice())
+      .collect::<Result<_, _>>()
+      .unwrap();
+    assert_eq!(read.len(), 2);
+    assert_eq!(read[0], (0, docs[0].clone()));
+    assert_eq!(read[1], (bytes.len() as u64 - 28, docs[1].clone()));
+  }
+
+  // `depth` documents nested in each other, written without recursing.
+  fn nested(depth: usize) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for level in (1..depth).rev() {
+      let length = MIN_DOCUMENT_LENGTH + 8 * level;
+      bytes.extend_from_slice(&(length as i32).to_le_bytes());
+      bytes.extend_from_slice(&[0x03, b'a', 0x00]);
+    }
+    bytes.extend_from_slice(&[0x05, 0x00, 0x00, 0x00, 0x00]);
+    bytes.resize(bytes.len() + depth - 1, 0x00);
+    bytes
+  }
+
+  #[test]
+  fn it_rejects_documents_nested_too_deeply() {
+    let doc = decode(&nested(MAX_BSON_DEPTH)).unwrap();
+    assert!(doc.contains_key("a"));
+    assert!(decode(&nested(MAX_BSON_DEPTH + 1)).is_err());
+    // deep enough to overflow the stack if it were decoded
+    let bytes = nested(100_000);
+    match BsonReader::new(bytes.as_slice()).read_block().unwrap() {
+      Some(Block::Malformed { error, .. }) => {
+        assert!(error.to_string().contains("nested deeper than 100"))
+      }
+      block => panic!("expected a malformed block, got {:?}", block),
+    }
+  }
+
+  #[test]
+  fn it_reads_empty_input() {
+    assert_eq!(BsonReader::new(&b""[..]).count(), 0);
+  }
+
+  #[test]
+  fn it_reports_offset_of_truncated_document() {
+    let first = bytes(&[doc! {"name": "Nori"}]).len();
+    let mut bytes = bytes(&[doc! {"name": "Nori"}, doc! {"name": "Rey"}]);
+    bytes.truncate(bytes.len() - 3);
+    let mut reader = BsonReader::new(bytes.as_slice());
+    assert!(reader.next().unwrap().is_ok());
+    let error = reader.next().unwrap().unwrap_err();
+    assert_eq!(
+      error.to_string(),
+      format!("byte offset {}: truncated BSON document", first)
+    );
+    assert!(reader.next().is_none());
+  }
+
+  #[test]
+  fn it_reports_offset_of_malformed_document() {
+    let first = bytes(&[doc! {"name": "Nori"}]).len();
+    let mut bytes = bytes(&[doc! {"name": "Nori"}, doc! {"name": "Rey"}]);
+    // replace the string type of the second document's first element
+    bytes[first + 4] = 0x42;
+    let mut reader = BsonReader::new(bytes.as_slice());
+    assert!(reader.next().unwrap().is_ok());
+    let error = reader.next().unwrap().unwrap_err();
+    assert!(matches!(error.kind(), ErrorKind::Bson(_)));
+    assert_eq!(error.location(), Some(Location::Offset(first as u64)));
+  }
+
+  fn read_all(reader: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    decompress(reader).unwrap().read_to_end(&mut bytes).unwrap();
+    bytes
+  }
+
+  #[test]
+  fn it_passes_through_uncompressed_input() {
+    assert_eq!(read_all(b"{}"), b"{}");
+    assert_eq!(read_all(b""), b"");
+  }
+
+  #[cfg(feature = "gzip")]
+  #[test]
+  fn it_decompresses_gzip() {
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    // two members, like `pigz` or concatenated files produce
+    let mut compressed = Vec::new();
+    for part in &[&b"{\"name\": "[..], &b"\"Nori\"}"[..]] {
+      let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+      encoder.write_all(part).unwrap();
+      compressed.extend(encoder.finish().unwrap());
+    }
+    assert_eq!(read_all(&compressed), b"{\"name\": \"Nori\"}");
+  }
+
+  #[cfg(not(feature = "gzip"))]
+  #[test]
+  fn it_needs_gzip_feature() {
+    let error = decompress(&[0x1f, 0x8b, 0x08, 0x00][..]).err().unwrap();
+    assert_eq!(
+      error.to_string(),
+      "input is gzip compressed, which needs the `gzip` feature"
+    );
+  }
+
+  #[cfg(feature = "zstd")]
+  #[test]
+  fn it_decompresses_zstd() {
+    let compressed = zstd::stream::encode_all(&b"{}"[..], 0).unwrap();
+    assert_eq!(read_all(&compressed), b"{}");
+  }
+}
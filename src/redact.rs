@@ -0,0 +1,177 @@
+use super::projection::matches;
+use super::{Bson, SchemaParserOptions, ValueType};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Field names that suggest personal data or secrets, redacted with
+/// `SchemaParserOptions::redact_sensitive`. A field is sensitive if its name,
+/// lowercased and without `_` or `-`, ends with one of these, i.e.
+/// `userEmail` or `refresh_token`.
+pub static SENSITIVE_NAMES: &[&str] = &[
+  "email",
+  "password",
+  "passwd",
+  "secret",
+  "token",
+  "apikey",
+  "ssn",
+  "socialsecuritynumber",
+  "phone",
+  "phonenumber",
+  "mobile",
+  "dob",
+  "dateofbirth",
+  "birthdate",
+  "birthday",
+  "creditcard",
+  "cardnumber",
+  "ccnumber",
+  "cvv",
+  "iban",
+  "passport",
+  "passportnumber",
+  "nationalid",
+  "taxid",
+];
+
+/// What the values of redacted fields are replaced with.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Redaction {
+  /// Values are not kept, so neither `unique` nor `has_duplicates` are
+  /// computed.
+  Drop,
+  /// Values are kept as the first 16 bytes of the HMAC-SHA256 of their BSON
+  /// encoding, keyed with `salt`, in hex. Equal values have equal hashes, so
+  /// `unique` and `has_duplicates` still count them, and hashes only change
+  /// with the salt.
+  Hash,
+}
+
+// Whether the values of the field at `path` are redacted.
+pub(crate) fn is_redacted(options: &SchemaParserOptions, path: &str) -> bool {
+  options.redact.iter().any(|pattern| matches(pattern, path))
+    || options.redact_sensitive && path.split('.').any(is_sensitive)
+}
+
+// Whether a field name is listed in `SENSITIVE_NAMES`.
+pub(crate) fn is_sensitive(name: &str) -> bool {
+  let name = normalise_name(name);
+  SENSITIVE_NAMES
+    .iter()
+    .any(|sensitive| name.ends_with(sensitive))
+}
+
+// Lowercases a field name and strips `_` and `-`, so that `user_email` and
+// `userEmail` compare the same.
+pub(crate) fn normalise_name(name: &str) -> String {
+  name
+    .chars()
+    .filter(|c| *c != '_' && *c != '-')
+    .flat_map(char::to_lowercase)
+    .collect()
+}
+
+// Returns `kept`, the value kept for `value`, or what it is replaced with if
+// the field at `path` is redacted.
+pub(crate) fn redact(
+  options: &SchemaParserOptions,
+  path: &str,
+  value: &Bson,
+  kept: ValueType,
+) -> Option<ValueType> {
+  if !is_redacted(options, path) {
+    return Some(kept);
+  }
+  match options.redaction {
+    Redaction::Drop => None,
+    Redaction::Hash => hash(&options.salt, value).map(ValueType::Str),
+  }
+}
+
+// Hashes `value` as the only element of a document with an empty name. That
+// is its type and its bytes as the BSON spec lays them out, so `1` and `"1"`
+// differ, and the hash doesn't depend on how this crate or bson show values.
+// Values BSON can't encode, i.e. a regex with a null byte, have no hash.
+fn hash(salt: &str, value: &Bson) -> Option<String> {
+  let mut message = Vec::new();
+  let mut doc = bson::Document::new();
+  doc.insert("", value.clone());
+  doc.to_writer(&mut message).ok()?;
+  // HMAC takes keys of any length
+  let mut mac = Hmac::<Sha256>::new_from_slice(salt.as_bytes()).ok()?;
+  mac.update(&message);
+  let hash = mac.finalize().into_bytes();
+  Some(
+    hash[..16]
+      .iter()
+      .map(|byte| format!("{:02x}", byte))
+      .collect(),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_finds_sensitive_names() {
+    assert!(is_sensitive("email"));
+    assert!(is_sensitive("userEmail"));
+    assert!(is_sensitive("refresh_token"));
+    assert!(is_sensitive("Date-Of-Birth"));
+    assert!(!is_sensitive("email_verified"));
+    assert!(!is_sensitive("name"));
+
+    let options = SchemaParserOptions {
+      redact_sensitive: true,
+      ..Default::default()
+    };
+    assert!(is_redacted(&options, "contact.phone"));
+    // everything inside a sensitive document
+    assert!(is_redacted(&options, "creditCard.expiry"));
+    assert!(!is_redacted(&options, "contact.name"));
+  }
+
+  #[test]
+  fn it_hashes_values() {
+    let options = SchemaParserOptions {
+      redact: vec!["**.email".to_string()],
+      redaction: Redaction::Hash,
+      salt: "pepper".to_string(),
+      ..Default::default()
+    };
+    let kept = ValueType::Str("kept".to_string());
+    let redact = |options: &SchemaParserOptions, path, value: Bson| {
+      redact(options, path, &value, kept.clone())
+    };
+    let email = Bson::String("nori@example.com".to_string());
+    let hash = redact(&options, "owner.email", email.clone()).unwrap();
+    // HMAC-SHA256 with the key `pepper` of the BSON document
+    // `{"": "nori@example.com"}`
+    assert_eq!(
+      hash,
+      ValueType::Str("d5af1f8c2864c1cf87d5df884d4f44f5".to_string())
+    );
+    assert_eq!(redact(&options, "email", email.clone()), Some(hash.clone()));
+    // the type is part of what is hashed
+    assert_ne!(
+      redact(&options, "email", Bson::Int32(1)),
+      redact(&options, "email", Bson::String("1".to_string()))
+    );
+    // the salt changes every hash
+    let salted = SchemaParserOptions {
+      salt: "salt".to_string(),
+      ..options.clone()
+    };
+    assert_ne!(redact(&salted, "email", email.clone()), Some(hash));
+    let name = Bson::String("Nori".to_string());
+    assert_eq!(redact(&options, "name", name), Some(kept.clone()));
+
+    let dropped = SchemaParserOptions {
+      redaction: Redaction::Drop,
+      ..options
+    };
+    assert_eq!(redact(&dropped, "email", email), None);
+  }
+}
@@ -1,3 +1,5 @@
+use std::cmp::Ordering;
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, PartialOrd)]
 #[serde(untagged)]
 pub enum ValueType {
@@ -11,3 +13,67 @@ pub enum ValueType {
   Boolean(bool),
   Null(String),
 }
+
+impl ValueType {
+  /// Same as `partial_cmp`, but total: doubles are compared with
+  /// `f64::total_cmp`, so NaN has a place in the order too.
+  pub fn total_cmp(&self, other: &Self) -> Ordering {
+    match (self, other) {
+      (ValueType::Str(a), ValueType::Str(b)) => a.cmp(b),
+      (ValueType::I32(a), ValueType::I32(b)) => a.cmp(b),
+      (ValueType::I64(a), ValueType::I64(b)) => a.cmp(b),
+      (ValueType::Decimal128(a), ValueType::Decimal128(b)) => a.cmp(b),
+      (ValueType::FloatingPoint(a), ValueType::FloatingPoint(b)) => {
+        a.total_cmp(b)
+      }
+      (ValueType::Array(a), ValueType::Array(b)) => a
+        .iter()
+        .zip(b.iter())
+        .map(|(a, b)| a.total_cmp(b))
+        .find(|ordering| *ordering != Ordering::Equal)
+        .unwrap_or_else(|| a.len().cmp(&b.len())),
+      (ValueType::Binary(a), ValueType::Binary(b)) => a.cmp(b),
+      (ValueType::Boolean(a), ValueType::Boolean(b)) => a.cmp(b),
+      (ValueType::Null(a), ValueType::Null(b)) => a.cmp(b),
+      _ => self.variant().cmp(&other.variant()),
+    }
+  }
+
+  // Position of the variant, which orders values of different types like the
+  // derived `PartialOrd` does.
+  fn variant(&self) -> u8 {
+    match self {
+      ValueType::Str(_) => 0,
+      ValueType::I32(_) => 1,
+      ValueType::I64(_) => 2,
+      ValueType::Decimal128(_) => 3,
+      ValueType::FloatingPoint(_) => 4,
+      ValueType::Array(_) => 5,
+      ValueType::Binary(_) => 6,
+      ValueType::Boolean(_) => 7,
+      ValueType::Null(_) => 8,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn it_orders_nan() {
+    let mut values = vec![
+      ValueType::FloatingPoint(f64::NAN),
+      ValueType::I32(1),
+      ValueType::FloatingPoint(1.5),
+      ValueType::FloatingPoint(f64::NAN),
+      ValueType::FloatingPoint(f64::NEG_INFINITY),
+    ];
+    values.sort_by(ValueType::total_cmp);
+    values.dedup_by(|a, b| a.total_cmp(b) == Ordering::Equal);
+    assert_eq!(values.len(), 4);
+    assert_eq!(values[0], ValueType::I32(1));
+    assert_eq!(values[1], ValueType::FloatingPoint(f64::NEG_INFINITY));
+    assert!(matches!(values[3], ValueType::FloatingPoint(n) if n.is_nan()));
+  }
+}
@@ -0,0 +1,197 @@
+#![cfg(feature = "cli")]
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+fn run(args: &[&str], stdin: &[u8]) -> (bool, String, String) {
+  let mut child = Command::new(env!("CARGO_BIN_EXE_mongodb-schema"))
+    .args(args)
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .unwrap();
+  child.stdin.take().unwrap().write_all(stdin).unwrap();
+  let output = child.wait_with_output().unwrap();
+  (
+    output.status.success(),
+    String::from_utf8(output.stdout).unwrap(),
+    String::from_utf8(output.stderr).unwrap(),
+  )
+}
+
+#[test]
+fn it_reads_ndjson_files() {
+  let (success, stdout, _) = run(&["tests/fixtures/compass/flat.ndjson"], b"");
+  assert!(success);
+  let schema: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+  assert_eq!(schema["count"], 4);
+}
+
+#[test]
+fn it_reads_bson_from_stdin() {
+  let mut bytes = Vec::new();
+  for name in &["Nori", "Rey"] {
+    bson::doc! {"name": name}.to_writer(&mut bytes).unwrap();
+  }
+  let (success, stdout, _) = run(&["--bson", "--format", "compass"], &bytes);
+  assert!(success);
+  let schema: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+  assert_eq!(schema["count"], 2);
+  assert_eq!(schema["fields"][0]["name"], "name");
+}
+
+#[test]
+fn it_reports_the_failing_line() {
+  let (success, _, stderr) = run(&[], b"{\"name\": \"Nori\"}\n\n{\"name\":\n");
+  assert!(!success);
+  assert!(stderr.starts_with("mongodb-schema: -: line 3: "));
+}
+
+#[test]
+fn it_skips_malformed_lines() {
+  let input = b"{\"name\": \"Nori\"}\n{\"name\":\n{\"name\": \"Rey\"}\n";
+  let (success, stdout, stderr) = run(&["--lenient"], input);
+  assert!(success);
+  let schema: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+  assert_eq!(schema["count"], 2);
+  assert!(stderr.starts_with("mongodb-schema: skipped\nline 2: "));
+}
+
+#[test]
+fn it_reads_archives() {
+  let mut bytes = 0x8199_e26du32.to_le_bytes().to_vec();
+  let mut block = |docs: Vec<bson::Document>| {
+    for doc in docs {
+      doc.to_writer(&mut bytes).unwrap();
+    }
+    bytes.extend_from_slice(&[0xff; 4]);
+  };
+  block(vec![
+    bson::doc! {"version": "0.1"},
+    bson::doc! {"db": "zoo", "collection": "cats"},
+    bson::doc! {"db": "zoo", "collection": "dogs"},
+  ]);
+  block(vec![
+    bson::doc! {"db": "zoo", "collection": "cats"},
+    bson::doc! {"name": "Nori"},
+  ]);
+  block(vec![
+    bson::doc! {"db": "zoo", "collection": "cats", "EOF": true},
+  ]);
+  block(vec![
+    bson::doc! {"db": "zoo", "collection": "dogs", "EOF": true},
+  ]);
+
+  let (success, stdout, _) = run(&["--archive"], &bytes);
+  assert!(success);
+  let schemas: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+  assert_eq!(schemas["zoo.cats"]["count"], 1);
+  assert_eq!(schemas["zoo.dogs"]["count"], 0);
+}
+
+#[test]
+fn it_skips_malformed_archive_documents() {
+  let mut bytes = 0x8199_e26du32.to_le_bytes().to_vec();
+  let mut block = |docs: Vec<bson::Document>, malformed: &[u8]| {
+    for doc in docs {
+      doc.to_writer(&mut bytes).unwrap();
+    }
+    bytes.extend_from_slice(malformed);
+    bytes.extend_from_slice(&[0xff; 4]);
+  };
+  block(
+    vec![
+      bson::doc! {"version": "0.1"},
+      bson::doc! {"db": "zoo", "collection": "cats"},
+    ],
+    &[],
+  );
+  // a string without its terminating null byte
+  block(
+    vec![
+      bson::doc! {"db": "zoo", "collection": "cats"},
+      bson::doc! {"name": "Nori"},
+    ],
+    &[
+      0x10, 0x00, 0x00, 0x00, 0x02, b'a', 0x00, 0x02, 0x00, 0x00, 0x00, b'b',
+      b'c', 0x00, 0x00, 0x00,
+    ],
+  );
+  block(
+    vec![bson::doc! {"db": "zoo", "collection": "cats", "EOF": true}],
+    &[],
+  );
+
+  let (success, _, _) = run(&["--archive"], &bytes);
+  assert!(!success);
+
+  let (success, stdout, stderr) = run(&["--archive", "--lenient"], &bytes);
+  assert!(success);
+  let schemas: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+  assert_eq!(schemas["zoo.cats"]["count"], 1);
+  assert!(stderr.starts_with("mongodb-schema: skipped in zoo.cats\nbyte "));
+}
+
+#[test]
+fn it_reads_json_arrays() {
+  let json = b"\n  [{\"name\": \"Nori\"},\n {\"name\": \"Rey\"}]\n";
+  let (success, stdout, _) = run(&[], json);
+  assert!(success);
+  let schema: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+  assert_eq!(schema["count"], 2);
+}
+
+#[test]
+fn it_reads_csv() {
+  let csv = b"name.string(),age.int32()\nNori,4\n";
+  let (success, stdout, _) = run(&["--csv", "--columns-have-types"], csv);
+  assert!(success);
+  let schema: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+  assert_eq!(schema["fields"]["age"]["bson_types"][0], "Int32");
+}
+
+#[test]
+fn it_limits_depth() {
+  let json = br#"{"owner": {"name": "Irina"}}"#;
+  let (success, stdout, _) = run(&["--max-depth", "1"], json);
+  assert!(success);
+  let schema: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+  let owner = &schema["fields"]["owner"]["types"]["Document"];
+  assert_eq!(owner["count"], 1);
+  assert!(owner.get("fields").is_none());
+}
+
+#[test]
+fn it_excludes_fields() {
+  let json = br#"{"name": "Nori", "payload": {"blob": "..."}}"#;
+  let args = ["--exclude", "payload.**", "--exclude", "name"];
+  let (success, stdout, _) = run(&args, json);
+  assert!(success);
+  let schema: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+  assert!(schema["fields"].get("name").is_none());
+  let payload = &schema["fields"]["payload"]["types"]["Document"];
+  assert_eq!(payload["fields"], serde_json::json!({}));
+}
+
+#[test]
+fn it_fails_past_limits() {
+  let json = b"{\"toys\": [1, 2, 3]}\n";
+  let args = ["--max-array-elements", "2", "--limit-errors"];
+  let (success, _, stderr) = run(&args, json);
+  assert!(!success);
+  assert!(stderr.contains("line 1, field 'toys'"));
+  let (success, stdout, _) = run(&args[..2], json);
+  assert!(success);
+  let schema: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+  assert_eq!(schema["fields"]["toys"]["types"]["Array"]["truncated"], 1);
+}
+
+#[test]
+fn it_redacts_sensitive_fields() {
+  let json = br#"{"name": "Nori", "email": "irina@example.com"}"#;
+  let (success, stdout, _) = run(&["--redact-sensitive"], json);
+  assert!(success);
+  assert!(!stdout.contains("irina@example.com"));
+  assert!(stdout.contains("Nori"));
+}
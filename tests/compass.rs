@@ -0,0 +1,125 @@
+use mongodb_schema_parser::SchemaParser;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Conformance corpus for the Compass output. Every `<name>.ndjson` in the
+// fixtures directory is analysed and compared against `<name>.json`, which
+// holds the output of the `mongodb-schema` version pinned in `package.json`
+// there for the same documents, as written by `generate.js`. Whole objects are
+// compared, so missing and extra keys both fail. Numbers only have to agree to
+// f32 precision, since node writes probabilities as doubles.
+// `Option::is_some_and` needs Rust 1.70
+#[allow(clippy::unnecessary_map_or)]
+fn fixtures() -> Vec<PathBuf> {
+  let dir =
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/compass");
+  let mut inputs: Vec<PathBuf> = fs::read_dir(dir)
+    .unwrap()
+    .map(|entry| entry.unwrap().path())
+    .filter(|path| path.extension().map_or(false, |ext| ext == "ndjson"))
+    .collect();
+  inputs.sort();
+  inputs
+}
+
+fn parse(input: &Path) -> SchemaParser {
+  let mut schema_parser = SchemaParser::new();
+  for line in fs::read_to_string(input).unwrap().lines() {
+    schema_parser.write_json(line).unwrap();
+  }
+  schema_parser
+}
+
+fn assert_same(expected: &Value, actual: &Value, at: &str) {
+  match (expected, actual) {
+    (Value::Object(expected), Value::Object(actual)) => {
+      let mut keys: Vec<&String> =
+        expected.keys().chain(actual.keys()).collect();
+      keys.sort();
+      keys.dedup();
+      for key in keys {
+        let path = format!("{}.{}", at, key);
+        match (expected.get(key), actual.get(key)) {
+          (Some(expected), Some(actual)) => {
+            assert_same(expected, actual, &path)
+          }
+          (Some(_), None) => panic!("missing key {}", path),
+          (None, _) => panic!("unexpected key {}", path),
+        }
+      }
+    }
+    (Value::Array(expected), Value::Array(actual)) => {
+      assert_eq!(expected.len(), actual.len(), "length of {}", at);
+      for (i, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+        assert_same(expected, actual, &format!("{}[{}]", at, i));
+      }
+    }
+    (Value::Number(expected), Value::Number(actual)) => {
+      let expected = expected.as_f64().unwrap() as f32;
+      let actual = actual.as_f64().unwrap() as f32;
+      assert_eq!(expected, actual, "value of {}", at);
+    }
+    _ => assert_eq!(expected, actual, "value of {}", at),
+  }
+}
+
+#[test]
+#[ignore = "needs the output of tests/fixtures/compass/generate.js"]
+fn it_matches_mongodb_schema_output() {
+  for input in fixtures() {
+    let expected = input.with_extension("json");
+    let expected = fs::read_to_string(&expected).unwrap_or_else(|_| {
+      panic!(
+        "{} is missing, run `npm install && npm run generate` in {}",
+        expected.display(),
+        input.parent().unwrap().display()
+      )
+    });
+    let expected: Value = serde_json::from_str(&expected).unwrap();
+    let actual: Value =
+      serde_json::from_str(&parse(&input).into_compass_json().unwrap())
+        .unwrap();
+    assert_same(&expected, &actual, &input.display().to_string());
+  }
+}
+
+#[test]
+fn it_agrees_with_native_output() {
+  for input in fixtures() {
+    let native: Value =
+      serde_json::from_str(&parse(&input).into_json().unwrap()).unwrap();
+    let compass: Value =
+      serde_json::from_str(&parse(&input).into_compass_json().unwrap())
+        .unwrap();
+    assert_eq!(native["count"], compass["count"]);
+
+    let compass_fields = compass["fields"].as_array().unwrap();
+    let native_fields = native["fields"].as_object().unwrap();
+    assert_eq!(native_fields.len(), compass_fields.len());
+
+    for compass_field in compass_fields {
+      let name = compass_field["name"].as_str().unwrap();
+      let native_field = &native_fields[name];
+      // native output counts missing documents as part of the field
+      assert_eq!(native_field["count"], compass_field["total_count"]);
+      for compass_type in compass_field["types"].as_array().unwrap() {
+        let bson_type = compass_type["bsonType"].as_str().unwrap();
+        if bson_type == "Null" || bson_type == "Undefined" {
+          continue;
+        }
+        let native_type = native_field["types"]
+          .as_object()
+          .unwrap()
+          .values()
+          .find(|t| {
+            mongodb_schema_parser::compass_type_name(
+              t["bson_type"].as_str().unwrap(),
+            ) == bson_type
+          })
+          .unwrap();
+        assert_eq!(native_type["count"], compass_type["count"]);
+      }
+    }
+  }
+}
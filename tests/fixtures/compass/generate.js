@@ -0,0 +1,35 @@
+// Writes the expected `<name>.json` for every `<name>.ndjson` next to this
+// file with the mongodb-schema version pinned in package.json, for
+// tests/compass.rs:
+//
+//   cd tests/fixtures/compass && npm install && npm run generate
+//
+// Lines are read as Extended JSON with `relaxed: false`, so numbers keep the
+// Int32 and Double types this crate gives them. Commit the output as written;
+// don't edit it by hand.
+const fs = require('fs');
+const path = require('path');
+const { EJSON } = require('bson');
+const { parseSchema } = require('mongodb-schema');
+
+async function main() {
+  const inputs = fs
+    .readdirSync(__dirname)
+    .filter((file) => file.endsWith('.ndjson'));
+
+  for (const input of inputs) {
+    const docs = fs
+      .readFileSync(path.join(__dirname, input), 'utf8')
+      .split('\n')
+      .filter((line) => line.trim() !== '')
+      .map((line) => EJSON.parse(line, { relaxed: false }));
+    const schema = await parseSchema(docs);
+    const output = path.join(__dirname, input.replace(/\.ndjson$/, '.json'));
+    fs.writeFileSync(output, JSON.stringify(schema, null, 2) + '\n');
+  }
+}
+
+main().catch((err) => {
+  console.error(err);
+  process.exit(1);
+});
@@ -0,0 +1,201 @@
+use bson::oid::ObjectId;
+use bson::spec::BinarySubtype;
+use bson::{Binary, Bson, DateTime, Decimal128, Document, Regex, Timestamp};
+use mongodb_schema_parser::{OnLimit, SchemaParser, SchemaParserOptions};
+use proptest::prelude::*;
+
+// No document may panic the parser, which in WASM turns into an
+// "unreachable" trap. Documents are built from every BSON type, including
+// NaN and infinite doubles and arrays nested in arrays.
+fn leaf() -> impl Strategy<Value = Bson> {
+  prop_oneof![
+    prop_oneof![
+      Just(f64::NAN),
+      Just(f64::INFINITY),
+      Just(f64::NEG_INFINITY),
+      Just(-0.0),
+      any::<f64>(),
+    ]
+    .prop_map(Bson::Double),
+    any::<i32>().prop_map(Bson::Int32),
+    any::<i64>().prop_map(Bson::Int64),
+    "[a-z]{0,3}".prop_map(Bson::String),
+    any::<bool>().prop_map(Bson::Boolean),
+    Just(Bson::Null),
+    Just(Bson::Undefined),
+    Just(Bson::MinKey),
+    Just(Bson::MaxKey),
+    any::<i64>()
+      .prop_map(|millis| Bson::DateTime(DateTime::from_millis(millis))),
+    any::<[u8; 12]>().prop_map(|id| Bson::ObjectId(ObjectId::from_bytes(id))),
+    any::<[u8; 16]>()
+      .prop_map(|bytes| Bson::Decimal128(Decimal128::from_bytes(bytes))),
+    (any::<u32>(), any::<u32>()).prop_map(|(time, increment)| {
+      Bson::Timestamp(Timestamp { time, increment })
+    }),
+    (
+      prop_oneof![Just(BinarySubtype::Generic), Just(BinarySubtype::Encrypted)],
+      prop::collection::vec(any::<u8>(), 0..4),
+    )
+      .prop_map(|(subtype, bytes)| Bson::Binary(Binary { subtype, bytes })),
+    "[a-z]{0,3}".prop_map(|pattern| {
+      Bson::RegularExpression(Regex {
+        pattern,
+        options: String::new(),
+      })
+    }),
+    "[a-z]{0,3}".prop_map(Bson::JavaScriptCode),
+    "[a-z]{0,3}".prop_map(Bson::Symbol),
+  ]
+}
+
+fn value() -> impl Strategy<Value = Bson> {
+  leaf().prop_recursive(6, 64, 4, |inner| {
+    prop_oneof![
+      prop::collection::vec(inner.clone(), 0..4).prop_map(Bson::Array),
+      document(inner).prop_map(Bson::Document),
+    ]
+  })
+}
+
+// Few distinct keys, so that fields are seen with several types.
+fn document(
+  value: impl Strategy<Value = Bson>,
+) -> impl Strategy<Value = Document> {
+  prop::collection::vec(("[ab]", value), 0..4)
+    .prop_map(|fields| fields.into_iter().collect())
+}
+
+// A document holding documents (`true`) and arrays (`false`) nested in each
+// other in the order of `kinds`, written as BSON without recursing, since
+// encoding a `Document` that deep would overflow the stack itself.
+fn nested_bson(kinds: &[bool]) -> Vec<u8> {
+  let mut bytes = Vec::new();
+  for (level, document) in kinds.iter().enumerate() {
+    let length = 5 + 8 * (kinds.len() - level);
+    bytes.extend_from_slice(&(length as i32).to_le_bytes());
+    let (element_type, key) = if *document {
+      (0x03, b'a')
+    } else {
+      (0x04, b'0')
+    };
+    bytes.extend_from_slice(&[element_type, key, 0x00]);
+  }
+  bytes.extend_from_slice(&[0x05, 0x00, 0x00, 0x00, 0x00]);
+  bytes.resize(bytes.len() + kinds.len(), 0x00);
+  bytes
+}
+
+// The same nesting as json.
+fn nested_json(kinds: &[bool]) -> String {
+  let mut json = String::new();
+  for document in kinds {
+    json.push_str(if *document { "{\"a\": " } else { "[" });
+  }
+  json.push_str("{}");
+  for document in kinds.iter().rev() {
+    json.push(if *document { '}' } else { ']' });
+  }
+  format!("{{\"a\": {}}}", json)
+}
+
+fn write(docs: &[Document]) -> SchemaParser {
+  let mut schema_parser = SchemaParser::new();
+  for doc in docs {
+    schema_parser.write_bson(doc.clone()).unwrap();
+  }
+  schema_parser
+}
+
+proptest! {
+  #[test]
+  fn it_never_panics(
+    before in prop::collection::vec(document(value()), 0..6),
+    after in prop::collection::vec(document(value()), 0..6),
+  ) {
+    let mut before = write(&before);
+    let mut after = write(&after);
+    before.flush();
+    after.flush();
+    // flushing twice must not count missing fields twice
+    before.flush();
+    let _ = before.diff(&after);
+    let _ = before.drift(&after);
+    let _ = before.anomalies();
+    let _ = before.pii();
+    let encryption = before.suggest_encryption();
+    let _ = (encryption.schema_map(), encryption.encrypted_fields());
+    before.clone().into_json().unwrap();
+    before.into_compass_json().unwrap();
+  }
+
+  #[test]
+  fn it_never_panics_with_limits(
+    docs in prop::collection::vec(document(value()), 0..6),
+    limit in 0..4usize,
+    error in any::<bool>(),
+  ) {
+    let options = SchemaParserOptions {
+      max_depth: Some(limit),
+      max_fields: Some(limit),
+      max_array_elements: Some(limit),
+      max_document_bytes: Some(limit * 16),
+      on_limit: if error { OnLimit::Error } else { OnLimit::Truncate },
+      ..Default::default()
+    };
+    let mut schema_parser = SchemaParser::with_options(options).unwrap();
+    for doc in docs {
+      let _ = schema_parser.write_bson(doc);
+    }
+    schema_parser.into_json().unwrap();
+  }
+
+  #[test]
+  fn it_checks_any_document(
+    docs in prop::collection::vec(document(value()), 0..6),
+    doc in document(value()),
+  ) {
+    let schema_parser = write(&docs).flush();
+    let _ = schema_parser.check_document(&doc);
+  }
+
+  #[test]
+  fn it_never_panics_on_bytes(
+    bytes in prop::collection::vec(any::<u8>(), 0..256),
+  ) {
+    let _ = SchemaParser::new().write_bson_reader(bytes.as_slice());
+    let _ = SchemaParser::new().write_json_reader(bytes.as_slice());
+    let _ = SchemaParser::from_archive(bytes.as_slice());
+  }
+
+  #[test]
+  fn it_never_panics_on_deep_nesting(
+    kinds in prop_oneof![
+      prop::collection::vec(any::<bool>(), 90..110),
+      prop::collection::vec(any::<bool>(), 0..5000),
+    ],
+    lenient in any::<bool>(),
+  ) {
+    let mut schema_parser = SchemaParser::new();
+    schema_parser.set_lenient(lenient);
+    let bytes = nested_bson(&kinds);
+    let written = schema_parser.write_bson_reader(bytes.as_slice());
+    // the outermost document is the first of the 100 levels BSON may have
+    prop_assert_eq!(written.is_ok(), lenient || kinds.len() < 100);
+    let _ = schema_parser.write_json_reader(nested_json(&kinds).as_bytes());
+    schema_parser.into_json().unwrap();
+  }
+}
+
+#[test]
+fn it_reads_deeply_nested_arrays() {
+  let mut value = Bson::Double(f64::NAN);
+  for _ in 0..100 {
+    value = Bson::Array(vec![value, Bson::Int32(1)]);
+  }
+  let mut doc = Document::new();
+  doc.insert("a", value);
+  let mut schema_parser = write(&[doc.clone(), doc]);
+  schema_parser.flush();
+  schema_parser.into_json().unwrap();
+}